@@ -1,17 +1,20 @@
 use std::{fs, error::Error};
 use std::env;
 
+pub mod matcher;
+
+pub use matcher::{CaseInsensitiveMatcher, LiteralMatcher, Matcher, RegexMatcher};
+
 // In the success case, we return a unit type () which is nothing,
 // and in the case of an error, we return an error.
 pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
+    // We build the matcher first, so an invalid regex is reported before we read the file.
+    let matcher = config.matcher()?;
+
     // If error occurs, this error will automatically be returned.
     let contents = fs::read_to_string(config.filename)?;
 
-    let results = if config.case_sensitive {
-        search(&config.query, &contents)
-    } else {
-        search_case_insensitive(&config.query, &contents)
-    };
+    let results = search_with(matcher.as_ref(), &contents);
 
     for line in results {
         println!("{}", line);
@@ -20,33 +23,89 @@ pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PatternSyntax {
+    /// The patterns are plain text (-F). This is the default.
+    Fixed,
+    /// The patterns are regular expressions (-E).
+    Regex,
+}
+
 pub struct Config {
-    pub query: String,
+    pub patterns: Vec<String>,
     pub filename: String,
     pub case_sensitive: bool,
+    pub syntax: PatternSyntax,
 }
 
 impl Config {
     pub fn new(args: &[String]) -> Result<Config, &str> {
-        if args.len() < 3 {
+        let mut patterns: Vec<String> = Vec::new();
+        let mut syntax = PatternSyntax::Fixed;
+        let mut positional: Vec<String> = Vec::new();
+
+        let mut i = 1;
+        while i < args.len() {
+            match args[i].as_str() {
+                "-E" => syntax = PatternSyntax::Regex,
+                "-F" => syntax = PatternSyntax::Fixed,
+                // Every -e adds another pattern, and a line matches if any of them match.
+                "-e" => {
+                    i += 1;
+                    if i == args.len() {
+                        return Err("-e needs a pattern")
+                    }
+                    patterns.push(args[i].clone());
+                }
+                // Everything after -- is positional, even if it starts with a dash.
+                "--" => {
+                    positional.extend_from_slice(&args[i + 1..]);
+                    break;
+                }
+                _ => positional.push(args[i].clone()),
+            }
+            i += 1;
+        }
+
+        // Without -e, the first positional argument is the query string.
+        if patterns.is_empty() {
+            if positional.is_empty() {
+                return Err("not enough arguments")
+            }
+            patterns.push(positional.remove(0));
+        }
+
+        if positional.is_empty() {
             return Err("not enough arguments")
         }
 
         // Clone() as we do not want to take ownership of the String.
         // Not efficient, but the most simple thing to do.
-        let query = args[1].clone();
-        let filename = args[2].clone();
+        let filename = positional[0].clone();
 
         let case_sensitive = env::var("CASE_INSENSITIVE").is_err();
 
-        Ok(Config { query, filename, case_sensitive })
+        Ok(Config { patterns, filename, case_sensitive, syntax })
+    }
+
+    /// Builds the matcher described by this config.
+    /// This can fail, if one of the patterns is not a valid regex.
+    pub fn matcher(&self) -> Result<Box<dyn Matcher>, regex::Error> {
+        let matcher: Box<dyn Matcher> = match self.syntax {
+            PatternSyntax::Fixed if self.case_sensitive => Box::new(LiteralMatcher::new(&self.patterns)),
+            PatternSyntax::Fixed => Box::new(CaseInsensitiveMatcher::new(&self.patterns)),
+            PatternSyntax::Regex => Box::new(RegexMatcher::new(&self.patterns, !self.case_sensitive)?),
+        };
+
+        Ok(matcher)
     }
 }
 
-pub fn search<'a>(query: &str, content: &'a str) -> Vec<&'a str> {
+/// Returns every line of `content` that the matcher matches.
+pub fn search_with<'a>(matcher: &dyn Matcher, content: &'a str) -> Vec<&'a str> {
     let mut results: Vec<&str> = Vec::new();
     for line in content.lines() {
-        if line.contains(query) {
+        if matcher.is_match(line) {
             results.push(line);
         }
     }
@@ -54,23 +113,22 @@ pub fn search<'a>(query: &str, content: &'a str) -> Vec<&'a str> {
     results
 }
 
-pub fn search_case_insensitive<'a>(query: &str, content: &'a str) -> Vec<&'a str> {
-    let query = query.to_lowercase();
-    let mut results: Vec<&str> = Vec::new();
-
-    for line in content.lines() {
-        if line.to_lowercase().contains(&query) {
-            results.push(line);
-        }
-    }
+pub fn search<'a>(query: &str, content: &'a str) -> Vec<&'a str> {
+    search_with(&LiteralMatcher::new(&[query.to_string()]), content)
+}
 
-    results
+pub fn search_case_insensitive<'a>(query: &str, content: &'a str) -> Vec<&'a str> {
+    search_with(&CaseInsensitiveMatcher::new(&[query.to_string()]), content)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
     #[test]
     fn case_sensitive() {
         let query = "duct";
@@ -94,4 +152,32 @@ Trust me.";
 
         assert_eq!(vec!["Rust:", "Trust me."], search_case_insensitive(query, contents));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn regex_with_several_patterns() {
+        let patterns = vec![r"^\w+:$".to_string(), "th?ree".to_string()];
+        let matcher = RegexMatcher::new(&patterns, false).unwrap();
+        let contents = "\
+Rust:
+safe, fast, productive.
+Pick three.
+Duct tape.";
+
+        assert_eq!(vec!["Rust:", "Pick three."], search_with(&matcher, contents));
+    }
+
+    #[test]
+    fn config_with_flags() {
+        let config = Config::new(&args(&["minigrep", "-E", "-e", "a+", "-e", "b", "poem.txt"])).unwrap();
+        assert_eq!(vec!["a+", "b"], config.patterns);
+        assert_eq!("poem.txt", config.filename);
+        assert_eq!(PatternSyntax::Regex, config.syntax);
+
+        let config = Config::new(&args(&["minigrep", "--", "-E", "poem.txt"])).unwrap();
+        assert_eq!(vec!["-E"], config.patterns);
+        assert_eq!(PatternSyntax::Fixed, config.syntax);
+
+        assert!(Config::new(&args(&["minigrep", "-e"])).is_err());
+        assert!(Config::new(&args(&["minigrep", "-e", "a"])).is_err());
+    }
+}
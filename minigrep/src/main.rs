@@ -14,7 +14,7 @@ fn main() {
         process::exit(1);
     });
 
-    println!("Searching for '{}'", config.query);
+    println!("Searching for '{}'", config.patterns.join("' or '"));
     println!("In file '{}':\n", config.filename);
 
     if let Err(e) = minigrep::run(config) {
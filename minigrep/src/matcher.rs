@@ -0,0 +1,258 @@
+// A Matcher knows how to find a pattern inside of a single line.
+//
+// By putting the different kinds of matching (plain text, case-insensitive text and regex)
+// behind one trait, search() does not need to care which one it is using. It just asks
+// the matcher where the next match is.
+
+use std::ops::Range;
+
+use regex::{Regex, RegexBuilder};
+
+pub trait Matcher {
+    /// Returns the byte range of the first match in `line`, that starts at or after `start`.
+    fn find_at(&self, line: &str, start: usize) -> Option<Range<usize>>;
+
+    fn is_match(&self, line: &str) -> bool {
+        self.find_at(line, 0).is_some()
+    }
+}
+
+/// Matches one or more fixed strings (-F). A line matches if it contains any of them.
+pub struct LiteralMatcher {
+    patterns: Vec<String>,
+}
+
+impl LiteralMatcher {
+    pub fn new(patterns: &[String]) -> LiteralMatcher {
+        LiteralMatcher { patterns: patterns.to_vec() }
+    }
+}
+
+impl Matcher for LiteralMatcher {
+    fn find_at(&self, line: &str, start: usize) -> Option<Range<usize>> {
+        // The leftmost match wins, and if two patterns start at the same place, the longest one.
+        self.patterns
+            .iter()
+            .filter_map(|pattern| {
+                line[start..]
+                    .find(pattern.as_str())
+                    .map(|i| start + i..start + i + pattern.len())
+            })
+            .min_by_key(|range| (range.start, usize::MAX - range.end))
+    }
+}
+
+/// Matches one or more fixed strings, ignoring case.
+///
+/// Characters are compared with Unicode simple case folding, which maps every character to
+/// exactly one other character: 'ẞ' and 'ß' are the same, and so are 'Σ', 'σ' and 'ς'.
+/// Because one character always folds to one character, a match can't end in the middle
+/// of a character, and the returned range points into the original line.
+///
+/// Simple folding does not turn 'ß' into "ss" (that is full case folding, which changes the
+/// length of the text), so "STRASSE" does not match "straße". The regex engine (-E -i) folds
+/// the same way, so both engines always agree. There are no language specific rules either:
+/// 'I' matches 'i', but the Turkish 'ı' and 'İ' only match themselves.
+pub struct CaseInsensitiveMatcher {
+    patterns: Vec<Vec<char>>,
+}
+
+impl CaseInsensitiveMatcher {
+    pub fn new(patterns: &[String]) -> CaseInsensitiveMatcher {
+        CaseInsensitiveMatcher {
+            patterns: patterns.iter().map(|pattern| pattern.chars().map(fold).collect()).collect(),
+        }
+    }
+}
+
+impl Matcher for CaseInsensitiveMatcher {
+    fn find_at(&self, line: &str, start: usize) -> Option<Range<usize>> {
+        line[start..]
+            .char_indices()
+            .map(|(i, _)| start + i)
+            .chain(std::iter::once(line.len()))
+            .find_map(|i| {
+                self.patterns
+                    .iter()
+                    .filter_map(|pattern| folded_prefix_len(&line[i..], pattern))
+                    .max()
+                    .map(|len| i..i + len)
+            })
+    }
+}
+
+/// Returns the simple case folding of `c`, or `c` itself if it has none.
+pub fn fold(c: char) -> char {
+    unicode_case_mapping::case_folded(c)
+        .and_then(|folded| char::from_u32(folded.get()))
+        .unwrap_or(c)
+}
+
+// If the folded `text` starts with `pattern`, returns how many bytes of `text` that covers.
+fn folded_prefix_len(text: &str, pattern: &[char]) -> Option<usize> {
+    let mut chars = text.char_indices();
+
+    for &want in pattern {
+        match chars.next() {
+            Some((_, c)) if fold(c) == want => {}
+            _ => return None,
+        }
+    }
+
+    Some(chars.next().map_or(text.len(), |(offset, _)| offset))
+}
+
+/// Matches one or more regular expressions (-E). Several patterns are combined with OR.
+pub struct RegexMatcher {
+    regex: Regex,
+}
+
+impl RegexMatcher {
+    pub fn new(patterns: &[String], case_insensitive: bool) -> Result<RegexMatcher, regex::Error> {
+        // (?:...) is a group that does not capture, so "a|b" and "c" become "(?:a|b)|(?:c)".
+        let combined = patterns
+            .iter()
+            .map(|pattern| format!("(?:{})", pattern))
+            .collect::<Vec<_>>()
+            .join("|");
+
+        let regex = RegexBuilder::new(&combined)
+            .case_insensitive(case_insensitive)
+            .build()?;
+
+        Ok(RegexMatcher { regex })
+    }
+}
+
+impl Matcher for RegexMatcher {
+    fn find_at(&self, line: &str, start: usize) -> Option<Range<usize>> {
+        self.regex.find_at(line, start).map(|m| m.range())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn patterns(patterns: &[&str]) -> Vec<String> {
+        patterns.iter().map(|p| p.to_string()).collect()
+    }
+
+    // Every engine has to pass the same tests for plain text patterns,
+    // so we run them against all of them (with regex special characters escaped).
+    fn case_sensitive_matchers(queries: &[&str]) -> Vec<Box<dyn Matcher>> {
+        let escaped: Vec<String> = queries.iter().map(|q| regex::escape(q)).collect();
+        vec![
+            Box::new(LiteralMatcher::new(&patterns(queries))),
+            Box::new(RegexMatcher::new(&escaped, false).unwrap()),
+        ]
+    }
+
+    fn case_insensitive_matchers(queries: &[&str]) -> Vec<Box<dyn Matcher>> {
+        let escaped: Vec<String> = queries.iter().map(|q| regex::escape(q)).collect();
+        vec![
+            Box::new(CaseInsensitiveMatcher::new(&patterns(queries))),
+            Box::new(RegexMatcher::new(&escaped, true).unwrap()),
+        ]
+    }
+
+    #[test]
+    fn finds_first_match() {
+        for matcher in case_sensitive_matchers(&["duct"]) {
+            assert_eq!(Some(15..19), matcher.find_at("safe, fast, productive.", 0));
+            assert_eq!(None, matcher.find_at("Duct tape.", 0));
+        }
+    }
+
+    #[test]
+    fn finds_match_after_start() {
+        for matcher in case_sensitive_matchers(&["ab"]) {
+            assert_eq!(Some(0..2), matcher.find_at("ab ab", 0));
+            assert_eq!(Some(3..5), matcher.find_at("ab ab", 1));
+            assert_eq!(None, matcher.find_at("ab ab", 4));
+        }
+    }
+
+    #[test]
+    fn several_patterns_are_combined_with_or() {
+        for matcher in case_sensitive_matchers(&["tape", "fast"]) {
+            assert!(matcher.is_match("Duct tape."));
+            assert!(matcher.is_match("safe, fast, productive."));
+            assert!(!matcher.is_match("Pick three."));
+            // The leftmost match wins, no matter the order of the patterns.
+            assert_eq!(Some(6..10), matcher.find_at("safe, fast, tape", 0));
+        }
+    }
+
+    #[test]
+    fn special_characters_are_literal() {
+        for matcher in case_sensitive_matchers(&["a.c"]) {
+            assert!(matcher.is_match("a.c"));
+            assert!(!matcher.is_match("abc"));
+        }
+    }
+
+    #[test]
+    fn ignores_case() {
+        for matcher in case_insensitive_matchers(&["rUsT"]) {
+            assert_eq!(Some(0..4), matcher.find_at("Rust:", 0));
+            assert_eq!(Some(1..5), matcher.find_at("Trust me.", 0));
+            assert_eq!(None, matcher.find_at("Pick three.", 0));
+        }
+    }
+
+    #[test]
+    fn case_insensitive_offsets_point_into_the_original_line() {
+        for matcher in case_insensitive_matchers(&["ümlaut"]) {
+            assert_eq!(Some(4..11), matcher.find_at("Das ÜMLAUT", 0));
+        }
+    }
+
+    #[test]
+    fn unicode_case_folding() {
+        for matcher in case_insensitive_matchers(&["STRAẞE"]) {
+            assert_eq!(Some(4..11), matcher.find_at("Die straße", 0));
+            // Only full case folding turns ß into ss.
+            assert!(!matcher.is_match("Die strasse"));
+        }
+
+        // Σ folds to σ, and so does the final ς.
+        for matcher in case_insensitive_matchers(&["ΣΟΦΌΣ"]) {
+            assert_eq!(Some(3..13), matcher.find_at("ο σοφός", 0));
+        }
+
+        // Without Turkish rules, I is i, and the dotted and dotless i are different letters.
+        for matcher in case_insensitive_matchers(&["istanbul"]) {
+            assert!(matcher.is_match("ISTANBUL"));
+            assert!(!matcher.is_match("İSTANBUL"));
+            assert!(!matcher.is_match("ıstanbul"));
+        }
+        for matcher in case_insensitive_matchers(&["İSTANBUL"]) {
+            assert_eq!(Some(3..12), matcher.find_at("in İstanbul", 0));
+        }
+    }
+
+    #[test]
+    fn case_folded_matches_can_be_sliced() {
+        // 'K' (Kelvin sign) is 3 bytes, and folds to the 1 byte 'k'.
+        for matcher in case_insensitive_matchers(&["k"]) {
+            let line = "5 \u{212A} and k";
+            let first = matcher.find_at(line, 0).unwrap();
+            let second = matcher.find_at(line, first.end).unwrap();
+            assert_eq!(("\u{212A}", "k"), (&line[first], &line[second]));
+        }
+    }
+
+    #[test]
+    fn regex_patterns() {
+        let matcher = RegexMatcher::new(&patterns(&["^Pick", r"t\w+e"]), false).unwrap();
+        assert!(matcher.is_match("Pick three."));
+        assert!(matcher.is_match("Duct tape."));
+        assert!(!matcher.is_match("Rust:"));
+    }
+
+    #[test]
+    fn invalid_regex_is_an_error() {
+        assert!(RegexMatcher::new(&patterns(&["(unclosed"]), false).is_err());
+    }
+}
@@ -0,0 +1,54 @@
+// Compares searching many files one at a time (-j1) with searching them in parallel.
+//
+// Run it with 'cargo bench'. We don't need a benchmark framework for this: we build a
+// directory with a few hundred files, search it with both settings a couple of times,
+// and print the best time of each.
+
+use std::fs;
+use std::io;
+use std::time::{Duration, Instant};
+
+use minigrep::{run_to, Config};
+
+const FILES: usize = 400;
+const LINES_PER_FILE: usize = 5_000;
+const RUNS: usize = 5;
+
+fn main() {
+    let dir = tempfile::tempdir().unwrap();
+    for i in 0..FILES {
+        let content: String = (0..LINES_PER_FILE)
+            .map(|line| format!("{} {} the quick brown fox jumps over the lazy dog\n", i, line))
+            .collect();
+        fs::write(dir.path().join(format!("{:04}.log", i)), content).unwrap();
+    }
+    let root = dir.path().to_str().unwrap();
+
+    let threads = std::thread::available_parallelism().map_or(4, usize::from).to_string();
+    let sequential = best_of(&["-r", "-j", "1", "-E", r"fox \w+ over", root]);
+    let parallel = best_of(&["-r", "-j", &threads, "-E", r"fox \w+ over", root]);
+
+    println!("{} files, {} lines each, best of {} runs", FILES, LINES_PER_FILE, RUNS);
+    println!("sequential (-j 1): {:>10.2?}", sequential);
+    println!("parallel   (-j {}): {:>10.2?}", threads, parallel);
+    println!("speedup:           {:>10.2}x", sequential.as_secs_f64() / parallel.as_secs_f64());
+}
+
+fn best_of(args: &[&str]) -> Duration {
+    (0..RUNS)
+        .map(|_| {
+            let config = Config::new(
+                std::iter::once("minigrep")
+                    .chain(args.iter().copied())
+                    .map(String::from),
+            )
+            .unwrap();
+
+            // The output goes nowhere, so we only measure the search itself.
+            let start = Instant::now();
+            run_to(config, &mut io::sink(), &|_| {}).unwrap();
+            start.elapsed()
+        })
+        .min()
+        .unwrap()
+}
@@ -0,0 +1,754 @@
+// Command line parsing for minigrep.
+//
+// All flags are described once in the FLAGS table below. The parser uses the table to know
+// which flags exist and which of them take a value, and --help is generated from the same table,
+// so the two can never get out of sync.
+
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, IsTerminal};
+use std::thread;
+
+use crate::searcher::{BuildError, Searcher};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PatternSyntax {
+    /// The patterns are plain text (-F). This is the default.
+    Fixed,
+    /// The patterns are regular expressions (-E).
+    Regex,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+/// What is searched as one unit, and printed when it matches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordMode {
+    /// Every line on its own. This is the default.
+    Line,
+    /// -U: the whole file at once, so a match can span several lines.
+    /// The lines a match touches are printed together.
+    Multiline,
+    /// --paragraph: every block of lines between blank lines, like the stanzas of a poem.
+    Paragraph,
+}
+
+/// The order in which the matching lines are printed (--sort).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortOrder {
+    /// In the order of the files, and of the lines in every file. This is the default.
+    Path,
+    /// The closest matches first (see --fuzzy). Lines that are just as close stay in path order.
+    Score,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Config {
+    pub patterns: Vec<String>,
+    pub paths: Vec<String>,
+    pub case_sensitive: bool,
+    pub syntax: PatternSyntax,
+    /// -v: select the lines that do NOT match.
+    pub invert_match: bool,
+    /// -n: prefix every line with its line number.
+    pub line_number: bool,
+    /// -c: only print how many lines matched.
+    pub count: bool,
+    /// -l: only print the names of files with a match.
+    pub files_with_matches: bool,
+    /// -w: a match has to be a whole word.
+    pub word_regexp: bool,
+    /// -x: a match has to be the whole line.
+    pub line_regexp: bool,
+    pub color: ColorChoice,
+    /// -r: search directories, and everything inside of them.
+    pub recursive: bool,
+    /// --include: only search files whose name matches one of these globs.
+    pub include: Vec<String>,
+    /// --exclude: skip files whose name matches one of these globs.
+    pub exclude: Vec<String>,
+    /// --no-ignore: also search files that .gitignore tells us to skip.
+    pub no_ignore: bool,
+    /// --hidden: also search hidden files and directories, whose names start with a dot.
+    pub hidden: bool,
+    /// --max-depth: how deep to descend into directories. The paths given are at depth 0.
+    pub max_depth: Option<usize>,
+    /// -A: lines to print after every match.
+    pub after_context: Option<usize>,
+    /// -B: lines to print before every match.
+    pub before_context: Option<usize>,
+    /// -C: lines to print before and after every match, unless -A or -B say otherwise.
+    pub context: Option<usize>,
+    /// -j: how many files to search at the same time. Defaults to the number of CPUs.
+    pub threads: Option<usize>,
+    /// --json: print every match as a JSON object, for other programs to read.
+    pub json: bool,
+    /// --replace: print every match replaced with this text. With -E, $1 is the first capture group.
+    pub replace: Option<String>,
+    /// --in-place: write the replacements back to the files, instead of printing them.
+    pub in_place: bool,
+    /// --backup: keep a copy of every file that --in-place changes, with this suffix added to its name.
+    pub backup: Option<String>,
+    /// --dry-run: print a diff of what --in-place would change, without changing anything.
+    pub dry_run: bool,
+    /// --fuzzy: match the patterns as plain text, with at most this many typos (the edit distance).
+    pub fuzzy: Option<usize>,
+    pub sort: SortOrder,
+    /// -z: also search inside of compressed files (.gz, .bz2 and .zst).
+    pub search_zip: bool,
+    /// --index: use the index from `minigrep index DIR` to skip files that can't match.
+    pub index: bool,
+    /// --follow: keep reading the file when it ends, and search what is added to it, like `tail -f`.
+    pub follow: bool,
+    /// -q: don't print anything, and stop at the first match. Only the exit code tells the result.
+    pub quiet: bool,
+    /// -s: don't print errors about files that don't exist or can't be read.
+    pub no_messages: bool,
+    /// -U and --paragraph: search more than one line at a time.
+    pub records: RecordMode,
+}
+
+/// Everything that can go wrong while parsing the command line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigError {
+    /// --help was given. Not really an error, but it means we should not search.
+    HelpRequested,
+    MissingPattern,
+    UnknownFlag(String),
+    MissingValue(String),
+    InvalidValue { flag: String, value: String },
+    /// A flag was given without another flag it depends on, like --in-place without --replace.
+    Requires { flag: String, needs: String },
+    /// Two flags that don't make sense together, like --follow with -c, which never gets to count.
+    Conflicts { flag: String, with: String },
+    /// --follow was given with several files, with -r, or without a file (standard input).
+    FollowNeedsOneFile,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::HelpRequested => write!(f, "help requested"),
+            ConfigError::MissingPattern => write!(f, "Didn't get a query string"),
+            ConfigError::UnknownFlag(flag) => write!(f, "unknown flag '{}'", flag),
+            ConfigError::MissingValue(flag) => write!(f, "flag '{}' needs a value", flag),
+            ConfigError::InvalidValue { flag, value } => {
+                write!(f, "invalid value '{}' for flag '{}'", value, flag)
+            }
+            ConfigError::Requires { flag, needs } => {
+                write!(f, "flag '{}' only works together with '{}'", flag, needs)
+            }
+            ConfigError::Conflicts { flag, with } => {
+                write!(f, "flag '{}' can't be used together with '{}'", flag, with)
+            }
+            ConfigError::FollowNeedsOneFile => write!(f, "flag '--follow' needs exactly one FILE to follow"),
+        }
+    }
+}
+
+impl Error for ConfigError {}
+
+struct Flag {
+    short: Option<char>,
+    long: &'static str,
+    value: Option<&'static str>,
+    help: &'static str,
+}
+
+impl Flag {
+    // Like grep, a bare --color means --color=auto. So its value can only be given after a '=',
+    // and `--color to poem.txt` searches for "to".
+    fn value_is_optional(&self) -> bool {
+        self.long == "color"
+    }
+}
+
+const FLAGS: &[Flag] = &[
+    Flag { short: Some('e'), long: "regexp", value: Some("PATTERN"), help: "Search for PATTERN. Can be given several times" },
+    Flag { short: Some('E'), long: "extended-regexp", value: None, help: "Patterns are regular expressions" },
+    Flag { short: Some('F'), long: "fixed-strings", value: None, help: "Patterns are plain strings (default)" },
+    Flag { short: Some('i'), long: "ignore-case", value: None, help: "Ignore case distinctions" },
+    Flag { short: Some('U'), long: "multiline", value: None, help: "Let matches span several lines" },
+    Flag { short: None, long: "paragraph", value: None, help: "Search and print paragraphs (separated by blank lines)" },
+    Flag { short: Some('v'), long: "invert-match", value: None, help: "Select non-matching lines" },
+    Flag { short: Some('w'), long: "word-regexp", value: None, help: "Only match whole words" },
+    Flag { short: Some('x'), long: "line-regexp", value: None, help: "Only match whole lines" },
+    Flag { short: Some('n'), long: "line-number", value: None, help: "Print the line number of every line" },
+    Flag { short: Some('c'), long: "count", value: None, help: "Only print a count of matching lines per file" },
+    Flag { short: Some('l'), long: "files-with-matches", value: None, help: "Only print the names of files with a match" },
+    Flag { short: Some('q'), long: "quiet", value: None, help: "Print nothing, exit with 0 at the first match" },
+    Flag { short: Some('s'), long: "no-messages", value: None, help: "Don't print errors about unreadable files" },
+    Flag { short: Some('r'), long: "recursive", value: None, help: "Search directories recursively (without hidden files, see --hidden)" },
+    Flag { short: None, long: "include", value: Some("GLOB"), help: "Only search files matching GLOB" },
+    Flag { short: None, long: "exclude", value: Some("GLOB"), help: "Skip files matching GLOB" },
+    Flag { short: None, long: "no-ignore", value: None, help: "Don't skip files listed in .gitignore" },
+    Flag { short: None, long: "hidden", value: None, help: "With -r, also search files and directories starting with a dot" },
+    Flag { short: None, long: "max-depth", value: Some("NUM"), help: "Descend at most NUM directories deep" },
+    Flag { short: Some('A'), long: "after-context", value: Some("NUM"), help: "Print NUM lines of context after every match" },
+    Flag { short: Some('B'), long: "before-context", value: Some("NUM"), help: "Print NUM lines of context before every match" },
+    Flag { short: Some('C'), long: "context", value: Some("NUM"), help: "Print NUM lines of context before and after every match" },
+    Flag { short: Some('j'), long: "threads", value: Some("NUM"), help: "Search NUM files in parallel (default: number of CPUs)" },
+    Flag { short: None, long: "json", value: None, help: "Print the results as JSON lines" },
+    Flag { short: None, long: "replace", value: Some("TEXT"), help: "Print every match replaced with TEXT ($1 is a capture group, numbered across all patterns)" },
+    Flag { short: None, long: "in-place", value: None, help: "Write the replacements back to the files" },
+    Flag { short: None, long: "backup", value: Some("SUFFIX"), help: "With --in-place, keep a copy of every changed file as FILE + SUFFIX" },
+    Flag { short: None, long: "dry-run", value: None, help: "Print a diff of the replacements, without changing any file" },
+    Flag { short: None, long: "fuzzy", value: Some("NUM"), help: "Also match with up to NUM typos (patterns are plain text)" },
+    Flag { short: None, long: "sort", value: Some("BY"), help: "Sort the matching lines by: path (default) or score" },
+    Flag { short: Some('z'), long: "search-zip", value: None, help: "Search inside compressed files (.gz, .bz2, .zst)" },
+    Flag { short: None, long: "index", value: None, help: "Only search files the index of 'minigrep index' says can match" },
+    Flag { short: None, long: "follow", value: None, help: "Keep searching what is added to FILE, like tail -f" },
+    Flag { short: None, long: "color", value: Some("WHEN"), help: "Color the output: auto, always or never" },
+    Flag { short: Some('h'), long: "help", value: None, help: "Print this help" },
+];
+
+/// Generates the --help text from the FLAGS table.
+pub fn help() -> String {
+    let mut help = String::from(
+        "Usage: minigrep [OPTIONS] PATTERN [FILE...]\n       minigrep [OPTIONS] -e PATTERN... [FILE...]\n       minigrep index [DIR]\n\nWith no FILE, or when FILE is -, read standard input.\n\nOptions:\n",
+    );
+
+    let names: Vec<String> = FLAGS
+        .iter()
+        .map(|flag| {
+            let short = match flag.short {
+                Some(c) => format!("-{}, ", c),
+                None => "    ".to_string(),
+            };
+            match flag.value {
+                Some(value) if flag.value_is_optional() => format!("{}--{}[=<{}>]", short, flag.long, value),
+                Some(value) => format!("{}--{} <{}>", short, flag.long, value),
+                None => format!("{}--{}", short, flag.long),
+            }
+        })
+        .collect();
+
+    let width = names.iter().map(String::len).max().unwrap_or(0);
+
+    for (name, flag) in names.iter().zip(FLAGS) {
+        help.push_str(&format!("  {:width$}  {}\n", name, flag.help, width = width));
+    }
+
+    help.push_str(&format!("  {:width$}  Treat everything after it as PATTERN or FILE\n", "--", width = width));
+    help
+}
+
+impl Config {
+    /// Parses the command line. The first item is the name of the program, and is skipped.
+    pub fn new(mut args: impl Iterator<Item = String>) -> Result<Config, ConfigError> {
+        args.next();
+
+        let mut config = Config {
+            patterns: Vec::new(),
+            paths: Vec::new(),
+            case_sensitive: env::var("CASE_INSENSITIVE").is_err(),
+            syntax: PatternSyntax::Fixed,
+            invert_match: false,
+            line_number: false,
+            count: false,
+            files_with_matches: false,
+            word_regexp: false,
+            line_regexp: false,
+            color: ColorChoice::Auto,
+            recursive: false,
+            include: Vec::new(),
+            exclude: Vec::new(),
+            no_ignore: false,
+            hidden: false,
+            max_depth: None,
+            after_context: None,
+            before_context: None,
+            context: None,
+            threads: None,
+            json: false,
+            replace: None,
+            in_place: false,
+            backup: None,
+            dry_run: false,
+            fuzzy: None,
+            sort: SortOrder::Path,
+            search_zip: false,
+            index: false,
+            follow: false,
+            quiet: false,
+            no_messages: false,
+            records: RecordMode::Line,
+        };
+        let mut positional = Vec::new();
+
+        while let Some(arg) = args.next() {
+            if arg == "--" {
+                // Everything after -- is positional, even if it starts with a dash.
+                positional.extend(args.by_ref());
+            } else if let Some(long) = arg.strip_prefix("--") {
+                // --context=2 or --context 2
+                let (name, inline_value) = match long.split_once('=') {
+                    Some((name, value)) => (name, Some(value.to_string())),
+                    None => (long, None),
+                };
+                let flag = FLAGS
+                    .iter()
+                    .find(|flag| flag.long == name)
+                    .ok_or_else(|| ConfigError::UnknownFlag(arg.clone()))?;
+
+                let value = match (flag.value, inline_value) {
+                    (Some(_), Some(value)) => Some(value),
+                    (Some(_), None) if flag.value_is_optional() => None,
+                    (Some(_), None) => Some(args.next().ok_or_else(|| ConfigError::MissingValue(arg.clone()))?),
+                    (None, Some(_)) => return Err(ConfigError::UnknownFlag(arg.clone())),
+                    (None, None) => None,
+                };
+                config.apply(flag, value)?;
+            } else if arg.len() > 1 && arg.starts_with('-') {
+                // Short flags can be bundled, like -in. A flag with a value takes the rest
+                // of the argument (-eFOO), or else the next argument (-e FOO).
+                for (i, c) in arg[1..].char_indices() {
+                    let flag = FLAGS
+                        .iter()
+                        .find(|flag| flag.short == Some(c))
+                        .ok_or_else(|| ConfigError::UnknownFlag(format!("-{}", c)))?;
+
+                    if flag.value.is_some() {
+                        let rest = &arg[1 + i + c.len_utf8()..];
+                        let value = if rest.is_empty() {
+                            args.next().ok_or_else(|| ConfigError::MissingValue(format!("-{}", c)))?
+                        } else {
+                            rest.to_string()
+                        };
+                        config.apply(flag, Some(value))?;
+                        break;
+                    }
+
+                    config.apply(flag, None)?;
+                }
+            } else {
+                positional.push(arg);
+            }
+        }
+
+        let mut positional = positional.into_iter();
+
+        // Without -e, the first positional argument is the query string.
+        if config.patterns.is_empty() {
+            config.patterns.push(positional.next().ok_or(ConfigError::MissingPattern)?);
+        }
+
+        config.paths.extend(positional);
+        if config.paths.is_empty() {
+            // Just like grep, we search the current directory with -r, and stdin ("-") otherwise.
+            let default = if config.recursive { "." } else { "-" };
+            config.paths.push(default.to_string());
+        }
+
+        config.check_dependencies()?;
+
+        Ok(config)
+    }
+
+    // Some flags only change what another flag does, so on their own they would silently do nothing.
+    fn check_dependencies(&self) -> Result<(), ConfigError> {
+        let dependencies = [
+            (self.in_place, "--in-place", self.replace.is_some(), "--replace"),
+            (self.dry_run, "--dry-run", self.replace.is_some(), "--replace"),
+            (self.backup.is_some(), "--backup", self.in_place, "--in-place"),
+        ];
+
+        for (given, flag, satisfied, needs) in dependencies {
+            if given && !satisfied {
+                return Err(ConfigError::Requires { flag: flag.to_string(), needs: needs.to_string() });
+            }
+        }
+
+        // --in-place and --dry-run replace the matches in the lines that match. With -v, the
+        // selected lines are the ones without a match, so there would be nothing to replace in them.
+        if self.invert_match {
+            let conflicts = [(self.in_place, "--in-place"), (self.dry_run, "--dry-run")];
+            if let Some((_, flag)) = conflicts.iter().find(|(given, _)| *given) {
+                return Err(ConfigError::Conflicts { flag: flag.to_string(), with: "--invert-match".to_string() });
+            }
+        }
+
+        // --fuzzy matches plain text with typos. It can't do regexes, whole words or whole lines,
+        // and silently ignoring those flags would find lines the user did not ask for.
+        if self.fuzzy.is_some() {
+            let conflicts = [
+                (self.syntax == PatternSyntax::Regex, "--extended-regexp"),
+                (self.word_regexp, "--word-regexp"),
+                (self.line_regexp, "--line-regexp"),
+            ];
+            if let Some((_, with)) = conflicts.iter().find(|(given, _)| *given) {
+                return Err(ConfigError::Conflicts { flag: "--fuzzy".to_string(), with: with.to_string() });
+            }
+        }
+
+        // A followed file never ends, so everything that waits for the end would wait forever.
+        // And standard input already ends only when whoever writes to it is done.
+        if self.follow {
+            if self.recursive || self.paths.len() != 1 || self.paths[0] == "-" {
+                return Err(ConfigError::FollowNeedsOneFile);
+            }
+
+            let conflicts = [
+                (self.count, "--count"),
+                (self.files_with_matches, "--files-with-matches"),
+                (self.sort == SortOrder::Score, "--sort=score"),
+                (self.in_place, "--in-place"),
+                (self.dry_run, "--dry-run"),
+            ];
+            if let Some((_, with)) = conflicts.iter().find(|(given, _)| *given) {
+                return Err(ConfigError::Conflicts { flag: "--follow".to_string(), with: with.to_string() });
+            }
+        }
+
+        // -U and --paragraph read the whole file before they search it, which a followed file
+        // never lets them do. And --in-place and --dry-run only change the file line by line.
+        let records = match self.records {
+            RecordMode::Line => None,
+            RecordMode::Multiline => Some("--multiline"),
+            RecordMode::Paragraph => Some("--paragraph"),
+        };
+        if let Some(records) = records {
+            let conflicts = [(self.follow, "--follow"), (self.in_place, "--in-place"), (self.dry_run, "--dry-run")];
+            if let Some((_, flag)) = conflicts.iter().find(|(given, _)| *given) {
+                return Err(ConfigError::Conflicts { flag: flag.to_string(), with: records.to_string() });
+            }
+        }
+
+        Ok(())
+    }
+
+    fn apply(&mut self, flag: &Flag, value: Option<String>) -> Result<(), ConfigError> {
+        match flag.long {
+            "regexp" => self.patterns.extend(value),
+            "extended-regexp" => self.syntax = PatternSyntax::Regex,
+            "fixed-strings" => self.syntax = PatternSyntax::Fixed,
+            "ignore-case" => self.case_sensitive = false,
+            "invert-match" => self.invert_match = true,
+            "word-regexp" => self.word_regexp = true,
+            "line-regexp" => self.line_regexp = true,
+            "line-number" => self.line_number = true,
+            "count" => self.count = true,
+            "files-with-matches" => self.files_with_matches = true,
+            "json" => self.json = true,
+            "replace" => self.replace = value,
+            "in-place" => self.in_place = true,
+            "backup" => self.backup = value,
+            "dry-run" => self.dry_run = true,
+            "search-zip" => self.search_zip = true,
+            "index" => self.index = true,
+            "follow" => self.follow = true,
+            "quiet" => self.quiet = true,
+            "no-messages" => self.no_messages = true,
+            "multiline" => self.records = RecordMode::Multiline,
+            "paragraph" => self.records = RecordMode::Paragraph,
+            "fuzzy" => self.fuzzy = Some(number("--fuzzy", value)?),
+            "sort" => {
+                let value = value.unwrap_or_default();
+                self.sort = match value.as_str() {
+                    "path" => SortOrder::Path,
+                    "score" => SortOrder::Score,
+                    _ => return Err(ConfigError::InvalidValue { flag: "--sort".to_string(), value }),
+                };
+            }
+            "recursive" => self.recursive = true,
+            "include" => self.include.extend(value),
+            "exclude" => self.exclude.extend(value),
+            "no-ignore" => self.no_ignore = true,
+            "hidden" => self.hidden = true,
+            "max-depth" => self.max_depth = Some(number("--max-depth", value)?),
+            "after-context" => self.after_context = Some(number("-A", value)?),
+            "before-context" => self.before_context = Some(number("-B", value)?),
+            "context" => self.context = Some(number("-C", value)?),
+            "threads" => match number("-j", value)? {
+                0 => return Err(ConfigError::InvalidValue { flag: "-j".to_string(), value: "0".to_string() }),
+                threads => self.threads = Some(threads),
+            },
+            "color" => {
+                let value = value.unwrap_or_else(|| "auto".to_string());
+                self.color = match value.as_str() {
+                    "auto" => ColorChoice::Auto,
+                    "always" => ColorChoice::Always,
+                    "never" => ColorChoice::Never,
+                    _ => return Err(ConfigError::InvalidValue { flag: "--color".to_string(), value }),
+                };
+            }
+            "help" => return Err(ConfigError::HelpRequested),
+            _ => unreachable!("flag --{} is in FLAGS, but not handled", flag.long),
+        }
+
+        Ok(())
+    }
+
+    /// How many lines of context to print before every match.
+    pub fn lines_before(&self) -> usize {
+        self.before_context.or(self.context).unwrap_or(0)
+    }
+
+    /// How many lines of context to print after every match.
+    pub fn lines_after(&self) -> usize {
+        self.after_context.or(self.context).unwrap_or(0)
+    }
+
+    /// How many files to search at the same time.
+    pub fn threads(&self) -> usize {
+        self.threads
+            .or_else(|| thread::available_parallelism().ok().map(usize::from))
+            .unwrap_or(1)
+    }
+
+    /// Whether to print colors, following --color.
+    ///
+    /// With "auto" (the default), we only print colors when stdout is a terminal, and the
+    /// NO_COLOR environment variable is not set (see https://no-color.org).
+    pub fn use_color(&self) -> bool {
+        match self.color {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                io::stdout().is_terminal() && env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+            }
+        }
+    }
+
+    /// Builds the Searcher described by this config.
+    /// This can fail, if one of the patterns is not a valid regex.
+    pub fn searcher(&self) -> Result<Searcher, BuildError> {
+        let (before, after) = crate::context_lines(self);
+
+        Searcher::builder()
+            .patterns(&self.patterns)
+            .syntax(self.syntax)
+            .ignore_case(!self.case_sensitive)
+            .invert_match(self.invert_match)
+            .word_regexp(self.word_regexp)
+            .line_regexp(self.line_regexp)
+            .fuzzy(self.fuzzy)
+            .context(before, after)
+            .search_zip(self.search_zip)
+            .records(self.records)
+            .build()
+    }
+}
+
+fn number(flag: &str, value: Option<String>) -> Result<usize, ConfigError> {
+    let value = value.unwrap_or_default();
+    value.parse().map_err(|_| ConfigError::InvalidValue { flag: flag.to_string(), value })
+}
+
+// Parses `args` as if they were given after "minigrep" on the command line.
+// The tests of every module build their Config with this.
+#[cfg(test)]
+pub(crate) fn parse(args: &[&str]) -> Result<Config, ConfigError> {
+    Config::new(
+        std::iter::once("minigrep")
+            .chain(args.iter().copied())
+            .map(String::from),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn positional_arguments() {
+        let config = parse(&["to", "poem.txt", "other.txt"]).unwrap();
+        assert_eq!(vec!["to"], config.patterns);
+        assert_eq!(vec!["poem.txt", "other.txt"], config.paths);
+        assert!(!config.invert_match);
+        assert_eq!(ColorChoice::Auto, config.color);
+    }
+
+    #[test]
+    fn boolean_flags() {
+        let config = parse(&["-i", "--invert-match", "-nc", "-lwx", "to", "poem.txt"]).unwrap();
+        assert!(!config.case_sensitive);
+        assert!(config.invert_match);
+        assert!(config.line_number);
+        assert!(config.count);
+        assert!(config.files_with_matches);
+        assert!(config.word_regexp);
+        assert!(config.line_regexp);
+    }
+
+    #[test]
+    fn flags_with_values() {
+        let config = parse(&["-e", "a", "-eb", "--regexp=c", "--context", "2", "poem.txt"]).unwrap();
+        assert_eq!(vec!["a", "b", "c"], config.patterns);
+        assert_eq!(vec!["poem.txt"], config.paths);
+        assert_eq!(Some(2), config.context);
+
+        let config = parse(&["--color=always", "-E", "a", "poem.txt"]).unwrap();
+        assert_eq!(ColorChoice::Always, config.color);
+        assert_eq!(PatternSyntax::Regex, config.syntax);
+    }
+
+    #[test]
+    fn bare_color_is_auto() {
+        let config = parse(&["--color=never", "--color", "to", "poem.txt"]).unwrap();
+        assert_eq!(ColorChoice::Auto, config.color);
+        assert_eq!(vec!["to"], config.patterns);
+        assert_eq!(vec!["poem.txt"], config.paths);
+        assert!(help().contains("--color[=<WHEN>]"));
+    }
+
+    #[test]
+    fn recursive_flags() {
+        let config = parse(&["-r", "--include=*.rs", "--exclude", "*.lock", "--max-depth", "2", "--hidden", "to"]).unwrap();
+        assert!(config.recursive);
+        assert!(config.hidden);
+        assert_eq!(vec!["."], config.paths);
+        assert_eq!(vec!["*.rs"], config.include);
+        assert_eq!(vec!["*.lock"], config.exclude);
+        assert_eq!(Some(2), config.max_depth);
+
+        assert_eq!(
+            Err(ConfigError::InvalidValue { flag: "--max-depth".to_string(), value: "deep".to_string() }),
+            parse(&["--max-depth=deep", "to", "src"])
+        );
+    }
+
+    #[test]
+    fn context_flags() {
+        let config = parse(&["-C", "3", "-A1", "to", "poem.txt"]).unwrap();
+        assert_eq!(3, config.lines_before());
+        assert_eq!(1, config.lines_after());
+
+        let config = parse(&["to", "poem.txt"]).unwrap();
+        assert_eq!(0, config.lines_before());
+        assert_eq!(0, config.lines_after());
+    }
+
+    #[test]
+    fn threads_flag() {
+        assert_eq!(4, parse(&["-j", "4", "to", "src"]).unwrap().threads());
+        assert!(parse(&["to", "src"]).unwrap().threads() >= 1);
+        assert!(parse(&["-j0", "to", "src"]).is_err());
+    }
+
+    #[test]
+    fn double_dash_ends_flags() {
+        let config = parse(&["--", "-v", "-file"]).unwrap();
+        assert_eq!(vec!["-v"], config.patterns);
+        assert_eq!(vec!["-file"], config.paths);
+        assert!(!config.invert_match);
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(Err(ConfigError::MissingPattern), parse(&[]));
+        assert_eq!(vec!["-"], parse(&["to"]).unwrap().paths);
+        assert_eq!(Err(ConfigError::UnknownFlag("-Q".to_string())), parse(&["-Q", "to", "poem.txt"]));
+        assert_eq!(Err(ConfigError::UnknownFlag("--nope".to_string())), parse(&["--nope"]));
+        assert_eq!(Err(ConfigError::MissingValue("-e".to_string())), parse(&["-e"]));
+        assert_eq!(
+            Err(ConfigError::InvalidValue { flag: "--color".to_string(), value: "pink".to_string() }),
+            parse(&["--color=pink", "to", "poem.txt"])
+        );
+        assert_eq!(Err(ConfigError::HelpRequested), parse(&["--help"]));
+    }
+
+    #[test]
+    fn replace_flags() {
+        let config = parse(&["--replace", "$1", "--in-place", "--backup=.orig", "to", "poem.txt"]).unwrap();
+        assert_eq!(Some("$1".to_string()), config.replace);
+        assert!(config.in_place);
+        assert_eq!(Some(".orig".to_string()), config.backup);
+
+        assert_eq!(
+            Err(ConfigError::Requires { flag: "--dry-run".to_string(), needs: "--replace".to_string() }),
+            parse(&["--dry-run", "to", "poem.txt"])
+        );
+        assert_eq!(
+            Err(ConfigError::Requires { flag: "--backup".to_string(), needs: "--in-place".to_string() }),
+            parse(&["--replace=x", "--backup", "~", "to", "poem.txt"])
+        );
+        assert_eq!(
+            Err(ConfigError::Conflicts { flag: "--in-place".to_string(), with: "--invert-match".to_string() }),
+            parse(&["--replace=x", "--in-place", "-v", "to", "poem.txt"])
+        );
+        assert_eq!(
+            Err(ConfigError::Conflicts { flag: "--dry-run".to_string(), with: "--invert-match".to_string() }),
+            parse(&["-v", "--replace=x", "--dry-run", "to", "poem.txt"])
+        );
+    }
+
+    #[test]
+    fn fuzzy_flags() {
+        let config = parse(&["--fuzzy", "2", "--sort=score", "conection", "app.log"]).unwrap();
+        assert_eq!(Some(2), config.fuzzy);
+        assert_eq!(SortOrder::Score, config.sort);
+        assert!(config.searcher().unwrap().matcher().is_match("connection refused"));
+
+        let long = "x".repeat(65);
+        let config = parse(&["--fuzzy=1", &long]).unwrap();
+        assert_eq!(Some(BuildError::PatternTooLong(long)), config.searcher().err());
+        assert_eq!(
+            Err(ConfigError::InvalidValue { flag: "--sort".to_string(), value: "size".to_string() }),
+            parse(&["--sort", "size", "to"])
+        );
+        for flag in ["-E", "-w", "-x"] {
+            assert!(matches!(
+                parse(&["--fuzzy=1", flag, "to", "poem.txt"]),
+                Err(ConfigError::Conflicts { flag, .. }) if flag == "--fuzzy"
+            ));
+        }
+        assert_eq!(
+            Err(ConfigError::Conflicts { flag: "--fuzzy".to_string(), with: "--word-regexp".to_string() }),
+            parse(&["-w", "--fuzzy", "1", "to", "poem.txt"])
+        );
+    }
+
+    #[test]
+    fn follow_flag() {
+        assert!(parse(&["--follow", "ERROR", "app.log"]).unwrap().follow);
+
+        assert_eq!(Err(ConfigError::FollowNeedsOneFile), parse(&["--follow", "ERROR"]));
+        assert_eq!(Err(ConfigError::FollowNeedsOneFile), parse(&["--follow", "ERROR", "a.log", "b.log"]));
+        assert_eq!(Err(ConfigError::FollowNeedsOneFile), parse(&["--follow", "-r", "ERROR", "logs"]));
+        assert_eq!(
+            Err(ConfigError::Conflicts { flag: "--follow".to_string(), with: "--count".to_string() }),
+            parse(&["--follow", "-c", "ERROR", "app.log"])
+        );
+    }
+
+    #[test]
+    fn record_flags() {
+        assert_eq!(RecordMode::Multiline, parse(&["-U", "to", "poem.txt"]).unwrap().records);
+        assert_eq!(RecordMode::Paragraph, parse(&["--paragraph", "to", "poem.txt"]).unwrap().records);
+        assert_eq!(
+            Err(ConfigError::Conflicts { flag: "--in-place".to_string(), with: "--paragraph".to_string() }),
+            parse(&["--paragraph", "--replace=2", "--in-place", "to", "poem.txt"])
+        );
+    }
+
+    #[test]
+    fn help_lists_every_flag() {
+        let help = help();
+        for flag in FLAGS {
+            assert!(help.contains(&format!("--{}", flag.long)));
+            assert!(help.contains(flag.help));
+        }
+    }
+
+    #[test]
+    fn word_and_line_matching() {
+        let config = parse(&["-w", "to", "poem.txt"]).unwrap();
+        let searcher = config.searcher().unwrap();
+        let matcher = searcher.matcher();
+        assert!(matcher.is_match("How dreary to be somebody!"));
+        assert!(!matcher.is_match("Are you nobody, too?"));
+
+        let config = parse(&["-x", "-i", "to an admiring bog!", "poem.txt"]).unwrap();
+        let searcher = config.searcher().unwrap();
+        let matcher = searcher.matcher();
+        assert!(matcher.is_match("To an admiring bog!"));
+        assert!(!matcher.is_match("To an admiring bog! Yes."));
+    }
+}
@@ -0,0 +1,118 @@
+// Searching inside of compressed files (-z), like rotated logs (app.log.1.gz).
+//
+// To know whether a file is compressed, we first look at its first few bytes: every format
+// starts with its own "magic bytes". Only if those don't tell us anything, we look at the
+// file extension. The decompressor is put in front of the reader, so the search still reads
+// the file line by line, and never has to hold the whole decompressed file in memory.
+
+use std::ffi::OsStr;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+
+use bzip2::bufread::MultiBzDecoder;
+use flate2::bufread::MultiGzDecoder;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Gzip,
+    Bzip2,
+    Zstd,
+}
+
+impl Format {
+    /// Detects the format from the first bytes of the file, or else from its extension.
+    pub fn detect(path: &Path, header: &[u8]) -> Option<Format> {
+        if header.starts_with(&[0x1f, 0x8b]) {
+            return Some(Format::Gzip);
+        }
+        if header.starts_with(b"BZh") {
+            return Some(Format::Bzip2);
+        }
+        if header.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            return Some(Format::Zstd);
+        }
+
+        match path.extension().and_then(OsStr::to_str) {
+            Some("gz") => Some(Format::Gzip),
+            Some("bz2") => Some(Format::Bzip2),
+            Some("zst") => Some(Format::Zstd),
+            _ => None,
+        }
+    }
+}
+
+/// Returns a reader that decompresses `reader` if it is compressed, or else `reader` itself.
+///
+/// If the data turns out to be broken, reading from the returned reader fails.
+pub fn decompress<'a>(path: &Path, mut reader: Box<dyn BufRead + 'a>) -> io::Result<Box<dyn BufRead + 'a>> {
+    let format = match Format::detect(path, reader.fill_buf()?) {
+        Some(format) => format,
+        None => return Ok(reader),
+    };
+
+    // A compressed file can be several compressed files put after each other
+    // (like with `cat a.gz b.gz`), so we use the decoders that read all of them.
+    Ok(match format {
+        Format::Gzip => Box::new(BufReader::new(MultiGzDecoder::new(reader))),
+        Format::Bzip2 => Box::new(BufReader::new(MultiBzDecoder::new(reader))),
+        Format::Zstd => Box::new(BufReader::new(zstd::stream::read::Decoder::with_buffer(reader)?)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+
+    const POEM: &str = "I'm nobody! Who are you?\nAre you nobody, too?\n";
+
+    fn compress(format: Format, text: &str) -> Vec<u8> {
+        match format {
+            Format::Gzip => {
+                let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(text.as_bytes()).unwrap();
+                encoder.finish().unwrap()
+            }
+            Format::Bzip2 => {
+                let mut encoder = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::default());
+                encoder.write_all(text.as_bytes()).unwrap();
+                encoder.finish().unwrap()
+            }
+            Format::Zstd => zstd::encode_all(text.as_bytes(), 0).unwrap(),
+        }
+    }
+
+    fn read(path: &str, bytes: Vec<u8>) -> io::Result<String> {
+        let mut text = String::new();
+        decompress(Path::new(path), Box::new(io::Cursor::new(bytes)))?.read_to_string(&mut text)?;
+        Ok(text)
+    }
+
+    #[test]
+    fn detects_formats_by_magic_bytes() {
+        for format in [Format::Gzip, Format::Bzip2, Format::Zstd] {
+            // The name does not matter, the content tells us the format.
+            assert_eq!(Some(format), Format::detect(Path::new("app.log"), &compress(format, POEM)));
+            assert_eq!(POEM, read("app.log", compress(format, POEM)).unwrap());
+        }
+    }
+
+    #[test]
+    fn detects_formats_by_extension() {
+        assert_eq!(Some(Format::Gzip), Format::detect(Path::new("app.log.1.gz"), b""));
+        assert_eq!(Some(Format::Zstd), Format::detect(Path::new("app.zst"), b"plain"));
+        assert_eq!(None, Format::detect(Path::new("poem.txt"), POEM.as_bytes()));
+
+        // Plain text is read as it is.
+        assert_eq!(POEM, read("poem.txt", POEM.as_bytes().to_vec()).unwrap());
+    }
+
+    #[test]
+    fn broken_data_is_an_error() {
+        let mut broken = compress(Format::Gzip, POEM);
+        broken.truncate(broken.len() / 2);
+        assert!(read("app.log.gz", broken).is_err());
+
+        assert!(read("app.log.bz2", b"not bzip2 at all".to_vec()).is_err());
+    }
+}
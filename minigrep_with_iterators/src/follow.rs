@@ -0,0 +1,245 @@
+// Following a file (--follow), like `tail -f`: when we reach the end of the file, we don't stop,
+// but wait until more is written to it, and search that as well.
+//
+// All of this is hidden behind the Read trait. A FollowReader never says that the file has
+// ended, it just waits until there is something new to read. So the Searcher reads it line by
+// line like any other file, and does not even know that it is following one.
+//
+// Log files are often rotated: the old file is renamed (app.log becomes app.log.1), and a new,
+// empty file is created with the old name. Or the file is truncated, and written from the start
+// again. So every time we reach the end, we also check whether the name now belongs to another
+// file (it has another inode), or whether the file got shorter than what we have already read.
+// In both cases, we start again from the beginning, and tell the user about it through the
+// Messages of the search.
+
+use std::fs::{self, File, Metadata};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+use crate::Messages;
+
+/// How often we look at the file when we can't be told about changes.
+pub const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Reads a file, and keeps waiting for more when it reaches the end.
+pub struct FollowReader<'a> {
+    path: PathBuf,
+    file: File,
+    identity: Option<(u64, u64)>,
+    position: u64,
+    waiter: Waiter,
+    messages: &'a Messages<'a>,
+}
+
+impl<'a> FollowReader<'a> {
+    /// Opens the file, and waits for changes with inotify where we can, or else by polling.
+    /// When the file is replaced or truncated, `messages` is told about it.
+    pub fn open(path: &Path, messages: &'a Messages<'a>) -> io::Result<FollowReader<'a>> {
+        FollowReader::with_waiter(path, Waiter::new(path), messages)
+    }
+
+    pub fn with_waiter(path: &Path, waiter: Waiter, messages: &'a Messages<'a>) -> io::Result<FollowReader<'a>> {
+        let file = File::open(path)?;
+        let identity = identity(&file.metadata()?);
+
+        Ok(FollowReader { path: path.to_path_buf(), file, identity, position: 0, waiter, messages })
+    }
+
+    // Called at the end of the file. Returns true if we start over, with the new file that now
+    // has our name, or with the same file after it was truncated.
+    fn reopen(&mut self) -> io::Result<bool> {
+        // While a log is rotated, there may be a moment without any file with our name.
+        // Then we wait until the new one is created.
+        let metadata = match fs::metadata(&self.path) {
+            Ok(metadata) => metadata,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(false),
+            Err(err) => return Err(err),
+        };
+
+        if identity(&metadata) != self.identity {
+            let file = match File::open(&self.path) {
+                Ok(file) => file,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(false),
+                Err(err) => return Err(err),
+            };
+            (self.messages)(&format!("{}: file was replaced, following the new file", self.path.display()));
+            self.identity = identity(&file.metadata()?);
+            self.file = file;
+            self.position = 0;
+            return Ok(true);
+        }
+
+        // We can only see a truncation if the file is shorter than what we have read by the
+        // time we look. If it was already written past that point again, we can't tell.
+        if metadata.len() < self.position {
+            (self.messages)(&format!("{}: file truncated", self.path.display()));
+            self.file.seek(SeekFrom::Start(0))?;
+            self.position = 0;
+            return Ok(true);
+        }
+
+        Ok(false)
+    }
+}
+
+impl Read for FollowReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        loop {
+            let read = self.file.read(buf)?;
+            if read > 0 {
+                self.position += read as u64;
+                return Ok(read);
+            }
+
+            if !self.reopen()? {
+                self.waiter.wait()?;
+            }
+        }
+    }
+}
+
+// What makes a file the same file, even after it was renamed: the device it is on, and its
+// inode number. Only Unix has those, so elsewhere we can only notice truncation.
+#[cfg(unix)]
+fn identity(metadata: &Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn identity(_metadata: &Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+/// Waits until the followed file may have changed.
+pub enum Waiter {
+    /// Linux tells us about every change in the directory of the file. We watch the directory,
+    /// and not the file itself, so we also hear about a new file that is created by a rotation.
+    #[cfg(target_os = "linux")]
+    Inotify(inotify::Inotify),
+    /// Everywhere else (or if inotify fails, for example because there are too many watches),
+    /// we just look again after a while.
+    Poll(Duration),
+}
+
+impl Waiter {
+    pub fn new(path: &Path) -> Waiter {
+        #[cfg(target_os = "linux")]
+        if let Ok(inotify) = Waiter::watch(path) {
+            return Waiter::Inotify(inotify);
+        }
+
+        #[cfg(not(target_os = "linux"))]
+        let _ = path;
+
+        Waiter::Poll(POLL_INTERVAL)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn watch(path: &Path) -> io::Result<inotify::Inotify> {
+        use inotify::{Inotify, WatchMask};
+
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+
+        let inotify = Inotify::init()?;
+        let changes = WatchMask::MODIFY | WatchMask::CREATE | WatchMask::MOVED_TO | WatchMask::DELETE;
+        inotify.watches().add(dir, changes)?;
+        Ok(inotify)
+    }
+
+    fn wait(&mut self) -> io::Result<()> {
+        match self {
+            // We don't look at what the events are about, any change in the directory makes
+            // us look at the file again. Events that happen while we read stay queued, so we
+            // can't miss a change between reaching the end and starting to wait.
+            #[cfg(target_os = "linux")]
+            Waiter::Inotify(inotify) => {
+                let mut buffer = [0; 4096];
+                inotify.read_events_blocking(&mut buffer)?;
+                Ok(())
+            }
+            Waiter::Poll(interval) => {
+                thread::sleep(*interval);
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{no_messages, Match, Searcher};
+    use std::io::{BufReader, Write};
+    use std::sync::mpsc;
+
+    fn append(path: &Path, text: &str) {
+        fs::OpenOptions::new().append(true).create(true).open(path).unwrap().write_all(text.as_bytes()).unwrap();
+    }
+
+    // Follows the file in another thread, and sends every matching line back,
+    // until `lines` lines were found.
+    fn follow(path: &Path, waiter: Waiter, lines: usize) -> mpsc::Receiver<String> {
+        let (sender, receiver) = mpsc::channel();
+        let reader = FollowReader::with_waiter(path, waiter, &no_messages).unwrap();
+
+        thread::spawn(move || {
+            let searcher = Searcher::builder().pattern("match").build().unwrap();
+            let mut found = 0;
+            // The reader never ends, so the sink stops the search with an error.
+            let _ = searcher.search_reader(BufReader::new(reader), &mut |m: &Match| {
+                sender.send(m.line.text.to_string()).unwrap();
+                found += 1;
+                match found < lines {
+                    true => Ok(()),
+                    false => Err(io::Error::other("done")),
+                }
+            });
+        });
+
+        receiver
+    }
+
+    fn follows_appends_truncation_and_rotation(waiter: impl FnOnce(&Path) -> Waiter) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.log");
+        fs::write(&path, "first match\nnothing\n").unwrap();
+
+        let lines = follow(&path, waiter(&path), 4);
+        let next = || lines.recv_timeout(Duration::from_secs(10)).unwrap();
+        assert_eq!("first match", next());
+
+        // A line that is written in two parts is only searched once it is complete.
+        append(&path, "second ");
+        thread::sleep(Duration::from_millis(50));
+        append(&path, "match\n");
+        assert_eq!("second match", next());
+
+        fs::write(&path, "match 3\n").unwrap();
+        assert_eq!("match 3", next());
+
+        fs::rename(&path, dir.path().join("app.log.1")).unwrap();
+        append(&path, "match in the new file\n");
+        assert_eq!("match in the new file", next());
+    }
+
+    #[test]
+    fn follows_with_polling() {
+        follows_appends_truncation_and_rotation(|_| Waiter::Poll(Duration::from_millis(10)));
+    }
+
+    // Uses inotify on Linux, unless it is not available here.
+    #[test]
+    fn follows_with_the_default_waiter() {
+        follows_appends_truncation_and_rotation(Waiter::new);
+    }
+}
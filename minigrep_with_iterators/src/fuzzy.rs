@@ -0,0 +1,270 @@
+// Approximate matching (--fuzzy N): a line matches if some part of it can be turned into the
+// pattern with at most N edits, where an edit is inserting, removing or changing one character
+// (the Levenshtein distance). So with --fuzzy 1, "conection" still finds "connection".
+//
+// Trying every part of every line would be very slow, so we use Myers' bit-parallel algorithm
+// (in the formulation by Hyyrö). The classic way to compute an edit distance fills a table
+// with one row per pattern character, and one column per character of the line. Myers noticed
+// that two cells next to each other only ever differ by -1, 0 or +1, so a whole column can be
+// stored as bits in a few u64 values, and the next column is computed with a handful of bit
+// operations. That is why a pattern can be at most 64 characters long.
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+use crate::matcher::{fold, Matcher};
+
+/// The longest pattern --fuzzy supports, one bit per character.
+pub const MAX_PATTERN_LEN: usize = u64::BITS as usize;
+
+/// Matches one or more patterns with at most `max_distance` edits.
+pub struct FuzzyMatcher {
+    patterns: Vec<Pattern>,
+    max_distance: usize,
+    case_sensitive: bool,
+}
+
+impl FuzzyMatcher {
+    /// Panics if a pattern is longer than MAX_PATTERN_LEN characters (Config checks that for us).
+    pub fn new(patterns: &[String], max_distance: usize, case_sensitive: bool) -> FuzzyMatcher {
+        let patterns = patterns
+            .iter()
+            .map(|pattern| {
+                let chars: Vec<char> = match case_sensitive {
+                    true => pattern.chars().collect(),
+                    false => pattern.chars().map(fold).collect(),
+                };
+                assert!(chars.len() <= MAX_PATTERN_LEN, "pattern is too long for --fuzzy");
+                Pattern::new(chars)
+            })
+            .collect();
+
+        FuzzyMatcher { patterns, max_distance, case_sensitive }
+    }
+
+    fn fold(&self, c: char) -> char {
+        match self.case_sensitive {
+            true => c,
+            false => fold(c),
+        }
+    }
+
+    // Finds the first match of one pattern, and returns it together with its distance.
+    fn find_pattern(&self, pattern: &Pattern, line: &str, start: usize) -> Option<(Range<usize>, usize)> {
+        // First we search forward to find where the match ends. Myers' algorithm tells us the
+        // smallest distance of any part of the line that ends at the current character.
+        let mut search = Columns::new(pattern.len());
+        let mut best = match pattern.len() <= self.max_distance {
+            // Deleting the whole pattern is allowed, so the empty string at `start` matches.
+            true => Some((start, pattern.len())),
+            false => None,
+        };
+
+        for (i, c) in line[start..].char_indices() {
+            let end = start + i + c.len_utf8();
+            let distance = search.step(pattern.eq(self.fold(c)), false);
+
+            // Once we have a match, we keep going while it gets better (or stays as good),
+            // so "hell" does not win over "hello" when we search for "hello" with --fuzzy 1.
+            match best {
+                None if distance <= self.max_distance => best = Some((end, distance)),
+                Some((_, best_distance)) if distance < best_distance => best = Some((end, distance)),
+                Some((_, best_distance)) if distance > best_distance => break,
+                _ => {}
+            }
+        }
+
+        let (end, distance) = best?;
+
+        // Then we search backward from the end, with the pattern reversed, to find where the
+        // match starts. This time the match has to start exactly at `end`, so we use the
+        // "anchored" variant, which gives the distance of the whole part we have seen so far.
+        let mut anchored = Columns::new(pattern.len());
+        let mut match_start = end;
+        let mut best_distance = pattern.len();
+
+        for (i, c) in line[start..end].char_indices().rev() {
+            let distance = anchored.step(pattern.reversed_eq(self.fold(c)), true);
+            if distance < best_distance {
+                best_distance = distance;
+                match_start = start + i;
+            }
+        }
+
+        Some((match_start..end, distance))
+    }
+}
+
+impl Matcher for FuzzyMatcher {
+    fn find_at(&self, line: &str, start: usize) -> Option<Range<usize>> {
+        self.patterns
+            .iter()
+            .filter_map(|pattern| self.find_pattern(pattern, line, start))
+            .map(|(range, _)| range)
+            .min_by_key(|range| (range.start, usize::MAX - range.end))
+    }
+
+    fn distance(&self, line: &str) -> usize {
+        self.patterns
+            .iter()
+            .map(|pattern| {
+                let mut search = Columns::new(pattern.len());
+                line.chars()
+                    .map(|c| search.step(pattern.eq(self.fold(c)), false))
+                    .fold(pattern.len(), usize::min)
+            })
+            .min()
+            .unwrap_or(0)
+    }
+}
+
+// A pattern, with a bit mask for every character in it: bit i is set if the i-th character of
+// the pattern is that character. The same masks for the reversed pattern are used to search
+// backward.
+struct Pattern {
+    len: usize,
+    masks: HashMap<char, u64>,
+    reversed_masks: HashMap<char, u64>,
+}
+
+impl Pattern {
+    fn new(chars: Vec<char>) -> Pattern {
+        let mut masks = HashMap::new();
+        let mut reversed_masks = HashMap::new();
+
+        for (i, &c) in chars.iter().enumerate() {
+            *masks.entry(c).or_insert(0) |= 1 << i;
+            *reversed_masks.entry(c).or_insert(0) |= 1 << (chars.len() - 1 - i);
+        }
+
+        Pattern { len: chars.len(), masks, reversed_masks }
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn eq(&self, c: char) -> u64 {
+        self.masks.get(&c).copied().unwrap_or(0)
+    }
+
+    fn reversed_eq(&self, c: char) -> u64 {
+        self.reversed_masks.get(&c).copied().unwrap_or(0)
+    }
+}
+
+// One column of the edit distance table, stored as the differences between its cells:
+// bit i of `plus` is set if cell i + 1 is one more than cell i, and bit i of `minus`
+// if it is one less. `distance` is the value of the last cell.
+struct Columns {
+    plus: u64,
+    minus: u64,
+    distance: usize,
+    last: u64,
+}
+
+impl Columns {
+    fn new(len: usize) -> Columns {
+        // The first column is 0, 1, 2, ..., len: every cell is one more than the one above it.
+        let last = match len {
+            0 => 0,
+            len => 1 << (len - 1),
+        };
+        Columns { plus: !0, minus: 0, distance: len, last }
+    }
+
+    // Moves to the next column, for a character of the line whose bits in the pattern are `eq`.
+    // Without `anchored`, the match may start anywhere, so the top cell of every column is 0.
+    // With `anchored`, the top cell goes up by one in every column, like in a normal edit distance.
+    fn step(&mut self, eq: u64, anchored: bool) -> usize {
+        if self.last == 0 {
+            // An empty pattern is always found, without any edits.
+            return 0;
+        }
+
+        let xv = eq | self.minus;
+        let xh = ((eq & self.plus).wrapping_add(self.plus) ^ self.plus) | eq;
+        let mut horizontal_plus = self.minus | !(xh | self.plus);
+        let mut horizontal_minus = self.plus & xh;
+
+        if horizontal_plus & self.last != 0 {
+            self.distance += 1;
+        } else if horizontal_minus & self.last != 0 {
+            self.distance -= 1;
+        }
+
+        horizontal_plus = (horizontal_plus << 1) | u64::from(anchored);
+        horizontal_minus <<= 1;
+
+        self.plus = horizontal_minus | !(xv | horizontal_plus);
+        self.minus = horizontal_plus & xv;
+        self.distance
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::find_spans;
+
+    fn fuzzy(pattern: &str, max_distance: usize) -> FuzzyMatcher {
+        FuzzyMatcher::new(&[pattern.to_string()], max_distance, true)
+    }
+
+    // The slow way to compute the smallest edit distance of any part of `text` to `pattern`.
+    fn brute_force(pattern: &str, text: &str) -> usize {
+        let pattern: Vec<char> = pattern.chars().collect();
+        let text: Vec<char> = text.chars().collect();
+        let mut previous: Vec<usize> = vec![0; text.len() + 1];
+
+        for (i, p) in pattern.iter().enumerate() {
+            let mut current = vec![i + 1; text.len() + 1];
+            for (j, t) in text.iter().enumerate() {
+                let change = previous[j] + usize::from(p != t);
+                current[j + 1] = change.min(previous[j + 1] + 1).min(current[j] + 1);
+            }
+            previous = current;
+        }
+
+        previous.into_iter().min().unwrap()
+    }
+
+    #[test]
+    fn finds_matches_with_typos() {
+        let matcher = fuzzy("connection", 1);
+        let line = "error: conection refused";
+
+        assert_eq!(Some(7..16), matcher.find_at(line, 0));
+        assert_eq!(1, matcher.distance(line));
+        assert!(!matcher.is_match("error: conecton refused"));
+        assert!(fuzzy("connection", 2).is_match("error: conecton refused"));
+    }
+
+    #[test]
+    fn prefers_the_closest_match() {
+        assert_eq!(Some(0..5), fuzzy("hello", 1).find_at("hello world", 0));
+        assert_eq!(Some(4..9), fuzzy("hello", 1).find_at("say hallo", 0));
+        assert_eq!(0, fuzzy("hello", 2).distance("hell, hello"));
+    }
+
+    #[test]
+    fn agrees_with_brute_force() {
+        let lines = ["kitten sitting", "Saturday Sunday", "rust trust crust", "", "aaaa", "abcabcabd"];
+        let patterns = ["sitting", "sunday", "rsut", "a", "abd", "xyz"];
+
+        for pattern in patterns {
+            for line in lines {
+                assert_eq!(brute_force(pattern, line), fuzzy(pattern, 0).distance(line), "{} in {}", pattern, line);
+            }
+        }
+    }
+
+    #[test]
+    fn spans_cover_the_approximate_match() {
+        let line = "Die Straße ist lang, die Strasse auch";
+        let matcher = FuzzyMatcher::new(&["STRASSE".to_string()], 2, false);
+        let found: Vec<&str> = find_spans(&matcher, line).into_iter().map(|span| &line[span]).collect();
+
+        assert_eq!(vec!["Straße", "Strasse"], found);
+    }
+}
@@ -0,0 +1,496 @@
+// A trigram index, to search the same directory many times without reading every file.
+//
+// `minigrep index DIR` reads every file in DIR once, and writes down which trigrams (groups of
+// three bytes that follow each other) appear in it. A line can only contain "nobody" if the file
+// contains "nob", "obo", "bod" and "ody", so with --index we first ask the index which files have
+// all trigrams of the pattern, and only search those. Everything is case folded first (see
+// matcher::fold()), so the same index works with and without -i.
+//
+// The index remembers the modification time and size of every file. Running `minigrep index`
+// again only reads the files that changed since then. And when searching, a file that changed
+// after the index was built (or that is not in the index at all) is always searched, so an old
+// index makes the search slower, but never makes it miss a match. A broken index file is treated
+// the same way: we warn about it, and search every file.
+//
+// A compressed file reads differently with and without -z, so for those we keep two sets of
+// trigrams: one of the bytes in the file, and one of the decompressed content. The search then
+// uses the one that matches how it will read the file.
+//
+// The index is stored in DIR/.minigrep-index, in a simple binary format:
+//
+//      "minigrep-index 2\n"
+//      number of files (u32), and for every file:
+//          path length (u32), path (UTF-8, relative to DIR)
+//          modified: seconds (u64) and nanoseconds (u32) since 1970, size (u64)
+//          number of trigrams (u32), trigrams (u32 each, sorted)
+//          compressed (u8, 0 or 1), and if it is 1:
+//              number of trigrams (u32), trigrams of the decompressed content (u32 each, sorted)
+//
+// All numbers are little endian.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+
+use ignore::WalkBuilder;
+use tempfile::NamedTempFile;
+
+use crate::matcher::fold;
+use crate::walk::Paths;
+use crate::{decompress, walk, Config, Messages, PatternSyntax};
+
+/// The name of the index file, inside of the indexed directory.
+pub const FILE_NAME: &str = ".minigrep-index";
+
+const MAGIC: &[u8] = b"minigrep-index 2\n";
+
+// No path is longer than this, so a longer one means that the index is broken. Without a limit,
+// a broken length could make us allocate gigabytes before we even notice.
+const MAX_PATH_LEN: usize = 64 * 1024;
+
+/// Returns the trigrams of `text` after case folding, sorted and without duplicates.
+pub fn trigrams(text: &str) -> Vec<u32> {
+    let folded: String = text.chars().map(fold).collect();
+    let mut trigrams: Vec<u32> = folded
+        .as_bytes()
+        .windows(3)
+        .map(|w| (w[0] as u32) << 16 | (w[1] as u32) << 8 | w[2] as u32)
+        .collect();
+
+    trigrams.sort_unstable();
+    trigrams.dedup();
+    trigrams
+}
+
+// What the index knows about one file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct FileEntry {
+    modified: Duration,
+    len: u64,
+    // The trigrams of the file as it is read without -z.
+    trigrams: Vec<u32>,
+    // For a compressed file, the trigrams of the content as it is read with -z.
+    unzipped: Option<Vec<u32>>,
+}
+
+impl FileEntry {
+    // Whether the file is still the same as when we indexed it.
+    fn is_fresh(&self, metadata: &fs::Metadata) -> bool {
+        modified_since_epoch(metadata) == Some(self.modified) && metadata.len() == self.len
+    }
+
+    fn contains_all(&self, trigrams: &[u32], search_zip: bool) -> bool {
+        let contained = match (search_zip, &self.unzipped) {
+            (true, Some(unzipped)) => unzipped,
+            _ => &self.trigrams,
+        };
+        trigrams.iter().all(|trigram| contained.binary_search(trigram).is_ok())
+    }
+}
+
+/// What `minigrep index` did.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct UpdateSummary {
+    pub files: usize,
+    /// Files that were new or changed, and had to be read again.
+    pub updated: usize,
+    /// Files that were in the index, but don't exist anymore.
+    pub removed: usize,
+}
+
+/// The trigrams of every file in one directory, by their path relative to that directory.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Index {
+    files: HashMap<PathBuf, FileEntry>,
+}
+
+impl Index {
+    /// Loads the index of `root`. If there is none yet, the index is empty.
+    pub fn load(root: &Path) -> io::Result<Index> {
+        match File::open(root.join(FILE_NAME)) {
+            Ok(file) => Index::read_from(BufReader::new(file)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Index::default()),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Builds or updates the index of `root`, and saves it.
+    /// Only files that are new or changed since the last time are read. A broken index, or a
+    /// file that can't be read, is given to `messages`, and does not stop the update.
+    pub fn update(root: &Path, messages: &Messages) -> Result<UpdateSummary, Box<dyn Error>> {
+        // A broken index is built again from scratch.
+        let old = Index::load(root).unwrap_or_else(|err| {
+            messages(&format!("{}: broken index, reading every file again: {}", root.join(FILE_NAME).display(), err));
+            Index::default()
+        });
+        let mut new = Index::default();
+        let mut summary = UpdateSummary::default();
+
+        // The same files as `minigrep -r` searches, so .gitignore is respected.
+        let walker = WalkBuilder::new(root)
+            .require_git(false)
+            .filter_entry(|entry| entry.file_name() != FILE_NAME)
+            .build();
+
+        // Just like with -r, a file or directory we can't get to is reported, and skipped.
+        for entry in walker {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(err) => {
+                    messages(&err);
+                    continue;
+                }
+            };
+            if !entry.file_type().is_some_and(|t| t.is_file()) {
+                continue;
+            }
+
+            let relative = entry.path().strip_prefix(root)?.to_path_buf();
+            let metadata = match entry.metadata() {
+                Ok(metadata) => metadata,
+                Err(err) => {
+                    messages(&err);
+                    continue;
+                }
+            };
+
+            // Such a path could not be read back, so the file is left out, and always searched.
+            if relative.as_os_str().len() > MAX_PATH_LEN {
+                continue;
+            }
+
+            let file = match old.files.get(&relative) {
+                Some(file) if file.is_fresh(&metadata) => file.clone(),
+                _ => match index_file(entry.path(), &metadata) {
+                    Ok(file) => {
+                        summary.updated += 1;
+                        file
+                    }
+                    // A file we can't read is left out, so it is always searched.
+                    Err(err) => {
+                        messages(&format!("{}: {}", entry.path().display(), err));
+                        continue;
+                    }
+                },
+            };
+
+            new.files.insert(relative, file);
+        }
+
+        summary.files = new.files.len();
+        summary.removed = old.files.keys().filter(|path| !new.files.contains_key(*path)).count();
+
+        new.save(root)?;
+        Ok(summary)
+    }
+
+    /// Saves the index to `root`. Like with --in-place, we write a temporary file first,
+    /// so a search at the same time never sees a half written index.
+    pub fn save(&self, root: &Path) -> io::Result<()> {
+        let temp = NamedTempFile::new_in(root)?;
+        let mut out = BufWriter::new(temp);
+        self.write_to(&mut out)?;
+
+        let temp = out.into_inner().map_err(|err| err.into_error())?;
+        temp.persist(root.join(FILE_NAME)).map_err(|err| err.error)?;
+        Ok(())
+    }
+
+    /// Whether the file at `relative` can contain any of the trigram sets in `queries`.
+    /// Files that are not in the index, or changed since, always can.
+    fn may_contain(&self, relative: &Path, metadata: &fs::Metadata, queries: &[Vec<u32>], search_zip: bool) -> bool {
+        match self.files.get(relative) {
+            Some(file) if file.is_fresh(metadata) => queries.iter().any(|query| file.contains_all(query, search_zip)),
+            _ => true,
+        }
+    }
+
+    fn write_to(&self, out: &mut impl Write) -> io::Result<()> {
+        // Sorted, so the same files always give the same index file.
+        let mut files: Vec<(&PathBuf, &FileEntry)> = self.files.iter().collect();
+        files.sort_by_key(|(path, _)| *path);
+
+        out.write_all(MAGIC)?;
+        write_u32(out, files.len())?;
+
+        for (path, file) in files {
+            let path = path.to_string_lossy();
+            write_u32(out, path.len())?;
+            out.write_all(path.as_bytes())?;
+            out.write_all(&file.modified.as_secs().to_le_bytes())?;
+            out.write_all(&file.modified.subsec_nanos().to_le_bytes())?;
+            out.write_all(&file.len.to_le_bytes())?;
+            write_trigrams(out, &file.trigrams)?;
+            match &file.unzipped {
+                Some(unzipped) => {
+                    out.write_all(&[1])?;
+                    write_trigrams(out, unzipped)?;
+                }
+                None => out.write_all(&[0])?,
+            }
+        }
+
+        Ok(())
+    }
+
+    fn read_from(mut input: impl Read) -> io::Result<Index> {
+        let mut magic = [0; MAGIC.len()];
+        input.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not a minigrep index"));
+        }
+
+        let mut index = Index::default();
+        for _ in 0..read_u32(&mut input)? {
+            let len = read_u32(&mut input)? as usize;
+            if len > MAX_PATH_LEN {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "broken minigrep index"));
+            }
+            let mut path = vec![0; len];
+            input.read_exact(&mut path)?;
+            let path = String::from_utf8(path).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+
+            let modified = Duration::new(read_u64(&mut input)?, read_u32(&mut input)?);
+            let len = read_u64(&mut input)?;
+            let trigrams = read_trigrams(&mut input)?;
+            let mut compressed = [0];
+            input.read_exact(&mut compressed)?;
+            let unzipped = match compressed[0] {
+                0 => None,
+                1 => Some(read_trigrams(&mut input)?),
+                _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "broken minigrep index")),
+            };
+
+            index.files.insert(PathBuf::from(path), FileEntry { modified, len, trigrams, unzipped });
+        }
+
+        Ok(index)
+    }
+}
+
+/// With --index, leaves out the files that the index says can't match.
+///
+/// The index can only help with plain text patterns (not with -E, --fuzzy or -v),
+/// and only when every pattern is at least three bytes long. Otherwise all files are kept.
+/// A missing or broken index is given to `messages`, and then every file is kept.
+pub fn candidates(config: &Config, paths: Paths, messages: &Messages) -> Result<Paths, Box<dyn Error>> {
+    let queries: Vec<Vec<u32>> = config.patterns.iter().map(|pattern| trigrams(pattern)).collect();
+    let usable = config.syntax == PatternSyntax::Fixed
+        && config.fuzzy.is_none()
+        && !config.invert_match
+        && queries.iter().all(|query| !query.is_empty());
+
+    if !usable {
+        return Ok(paths);
+    }
+
+    let mut roots = Vec::new();
+    for root in config.paths.iter().map(PathBuf::from).filter(|root| root.is_dir()) {
+        // Without a usable index, every file of the directory is searched, just like without --index.
+        let index = match Index::load(&root) {
+            Ok(index) => index,
+            Err(err) => {
+                messages(&format!("{}: ignoring broken index: {}", root.join(FILE_NAME).display(), err));
+                continue;
+            }
+        };
+        if index.files.is_empty() {
+            messages(&format!("{} has no index yet, run 'minigrep index {}' first", root.display(), root.display()));
+        }
+        roots.push((root, index));
+    }
+
+    let search_zip = config.search_zip;
+    Ok(Box::new(paths.filter(move |path| {
+        let path = match path {
+            Ok(path) => path,
+            Err(_) => return true,
+        };
+
+        let indexed = roots.iter().find_map(|(root, index)| Some((index, path.strip_prefix(root).ok()?)));
+        match (indexed, fs::metadata(path)) {
+            (Some((index, relative)), Ok(metadata)) => index.may_contain(relative, &metadata, &queries, search_zip),
+            _ => true,
+        }
+    })))
+}
+
+// Reads one file, and collects its trigrams. Compressed files are also indexed by their content,
+// so they can be found with -z. Binary files get no trigrams, because they are never searched.
+fn index_file(path: &Path, metadata: &fs::Metadata) -> io::Result<FileEntry> {
+    let bytes = fs::read(path)?;
+
+    // Just like the search, we only decompress when the file looks compressed.
+    let unzipped = match decompress::Format::detect(path, &bytes) {
+        Some(_) => {
+            let mut unzipped = Vec::new();
+            decompress::decompress(path, Box::new(&bytes[..]))?.read_to_end(&mut unzipped)?;
+            Some(text_trigrams(&unzipped))
+        }
+        None => None,
+    };
+
+    Ok(FileEntry {
+        modified: modified_since_epoch(metadata).unwrap_or_default(),
+        len: metadata.len(),
+        trigrams: text_trigrams(&bytes),
+        unzipped,
+    })
+}
+
+fn text_trigrams(bytes: &[u8]) -> Vec<u32> {
+    match walk::is_binary(bytes) {
+        true => Vec::new(),
+        false => trigrams(&String::from_utf8_lossy(bytes)),
+    }
+}
+
+fn modified_since_epoch(metadata: &fs::Metadata) -> Option<Duration> {
+    metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()
+}
+
+fn write_u32(out: &mut impl Write, value: usize) -> io::Result<()> {
+    let value = u32::try_from(value).map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+    out.write_all(&value.to_le_bytes())
+}
+
+fn write_trigrams(out: &mut impl Write, trigrams: &[u32]) -> io::Result<()> {
+    write_u32(out, trigrams.len())?;
+    for trigram in trigrams {
+        out.write_all(&trigram.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+fn read_trigrams(input: &mut impl Read) -> io::Result<Vec<u32>> {
+    (0..read_u32(input)?).map(|_| read_u32(input)).collect()
+}
+
+fn read_u32(input: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    input.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(input: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    input.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::parse;
+    use crate::no_messages;
+    use std::time::SystemTime;
+
+    fn found(config: &Config, root: &Path) -> Vec<String> {
+        let paths = crate::walk::paths(config).unwrap();
+        candidates(config, paths, &no_messages)
+            .unwrap()
+            .map(|path| path.unwrap().strip_prefix(root).unwrap().to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn trigrams_are_case_folded() {
+        assert_eq!(trigrams("Nobody"), trigrams("NOBODY"));
+        assert_eq!(4, trigrams("nobody").len());
+        assert!(trigrams("no").is_empty());
+    }
+
+    #[test]
+    fn index_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("poem.txt"), "I'm nobody! Who are you?").unwrap();
+
+        let summary = Index::update(dir.path(), &no_messages).unwrap();
+        assert_eq!(UpdateSummary { files: 1, updated: 1, removed: 0 }, summary);
+
+        let index = Index::load(dir.path()).unwrap();
+        let file = &index.files[Path::new("poem.txt")];
+        assert!(file.contains_all(&trigrams("NOBODY"), false));
+        assert!(!file.contains_all(&trigrams("somebody"), false));
+    }
+
+    #[test]
+    fn only_changed_files_are_read_again() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a.txt"), "alpha").unwrap();
+        fs::write(dir.path().join("b.txt"), "beta").unwrap();
+        Index::update(dir.path(), &no_messages).unwrap();
+
+        fs::write(dir.path().join("b.txt"), "beta, changed").unwrap();
+        fs::remove_file(dir.path().join("a.txt")).unwrap();
+        fs::write(dir.path().join("c.txt"), "gamma").unwrap();
+
+        let summary = Index::update(dir.path(), &no_messages).unwrap();
+        assert_eq!(UpdateSummary { files: 2, updated: 2, removed: 1 }, summary);
+    }
+
+    #[test]
+    fn index_narrows_the_files_to_search() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().to_str().unwrap();
+        fs::write(dir.path().join("a.txt"), "connection refused").unwrap();
+        fs::write(dir.path().join("b.txt"), "all good").unwrap();
+        Index::update(dir.path(), &no_messages).unwrap();
+
+        assert_eq!(vec!["a.txt"], found(&parse(&["-r", "--index", "-i", "REFUSED", root]).unwrap(), dir.path()));
+        // With -v, every file can have a line that does not match.
+        assert_eq!(vec!["a.txt", "b.txt"], found(&parse(&["-r", "--index", "-v", "refused", root]).unwrap(), dir.path()));
+
+        // A file that changed after indexing is always searched.
+        let b = dir.path().join("b.txt");
+        fs::write(&b, "refused as well").unwrap();
+        let later = SystemTime::now() + Duration::from_secs(10);
+        File::options().write(true).open(&b).unwrap().set_modified(later).unwrap();
+        assert_eq!(vec!["a.txt", "b.txt"], found(&parse(&["-r", "--index", "refused", root]).unwrap(), dir.path()));
+    }
+
+    #[test]
+    fn compressed_files_are_indexed_the_way_they_are_searched() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().to_str().unwrap();
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(b"connection refused").unwrap();
+        fs::write(dir.path().join("app.log.1.gz"), encoder.finish().unwrap()).unwrap();
+        // Not compressed at all, so it is searched as it is, with or without -z.
+        fs::write(dir.path().join("notes.txt"), "gzip it later").unwrap();
+        Index::update(dir.path(), &no_messages).unwrap();
+
+        assert_eq!(vec!["app.log.1.gz"], found(&parse(&["-r", "-z", "--index", "refused", root]).unwrap(), dir.path()));
+        assert!(found(&parse(&["-r", "--index", "refused", root]).unwrap(), dir.path()).is_empty());
+        assert_eq!(vec!["notes.txt"], found(&parse(&["-r", "-z", "--index", "gzip", root]).unwrap(), dir.path()));
+    }
+
+    #[test]
+    fn a_broken_index_searches_every_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().to_str().unwrap();
+        fs::write(dir.path().join("a.txt"), "connection refused").unwrap();
+        fs::write(dir.path().join("b.txt"), "all good").unwrap();
+        Index::update(dir.path(), &no_messages).unwrap();
+
+        // Cut off in the middle of the trigrams.
+        let index = fs::read(dir.path().join(FILE_NAME)).unwrap();
+        fs::write(dir.path().join(FILE_NAME), &index[..index.len() - 3]).unwrap();
+        assert!(Index::load(dir.path()).is_err());
+        assert_eq!(vec!["a.txt", "b.txt"], found(&parse(&["-r", "--index", "refused", root]).unwrap(), dir.path()));
+
+        // A path length that is far too long is not trusted either.
+        let mut index = MAGIC.to_vec();
+        index.extend(1u32.to_le_bytes());
+        index.extend(u32::MAX.to_le_bytes());
+        let err = Index::read_from(&index[..]).unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+
+        // And `minigrep index` just builds it again.
+        assert_eq!(2, Index::update(dir.path(), &no_messages).unwrap().updated);
+        assert_eq!(vec!["a.txt"], found(&parse(&["-r", "--index", "refused", root]).unwrap(), dir.path()));
+    }
+}
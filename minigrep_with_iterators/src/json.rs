@@ -0,0 +1,180 @@
+// Machine-readable output (--json), with one JSON object per line.
+//
+// The format follows the JSON lines that ripgrep prints, so tools that understand those
+// can read ours as well. Every object has a "type" and a "data" field:
+//
+//      {"type":"begin","data":{"path":{"text":"poem.txt"}}}
+//      {"type":"match","data":{"path":{"text":"poem.txt"},"lines":{"text":"Are you nobody, too?"},
+//          "line_number":2,"absolute_offset":25,"submatches":[{"match":{"text":"to"},"start":16,"end":18}]}}
+//      {"type":"context","data":{...same as match, without submatches...}}
+//      {"type":"end","data":{"path":{"text":"poem.txt"},"stats":{...}}}
+//      {"type":"summary","data":{"elapsed_total":{...},"stats":{...}}}
+
+use std::io::{self, Write};
+use std::ops::AddAssign;
+use std::time::Duration;
+
+use serde_json::{json, Value};
+
+use crate::{Line, Match};
+
+/// Counts what happened during a search, for the "end" and "summary" objects.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// How many files (or stdin) were searched.
+    pub searches: usize,
+    /// How many of those had at least one selected line.
+    pub searches_with_match: usize,
+    pub matched_lines: usize,
+    /// How many matches there were in total. A line can have more than one match.
+    pub matches: usize,
+    /// How many files could not be searched. This is not part of the JSON output (just like in
+    /// ripgrep), but it decides the exit code.
+    pub errors: usize,
+}
+
+impl AddAssign for Stats {
+    fn add_assign(&mut self, other: Stats) {
+        self.searches += other.searches;
+        self.searches_with_match += other.searches_with_match;
+        self.matched_lines += other.matched_lines;
+        self.matches += other.matches;
+        self.errors += other.errors;
+    }
+}
+
+pub fn begin(out: &mut impl Write, name: &str) -> io::Result<()> {
+    write_object(out, "begin", json!({ "path": text(name) }))
+}
+
+pub fn matched(out: &mut impl Write, name: &str, m: &Match) -> io::Result<()> {
+    let mut data = line_data(name, m.line);
+    data["submatches"] = m
+        .spans
+        .iter()
+        .map(|span| {
+            json!({
+                "match": text(&m.line.text[span.clone()]),
+                "start": span.start,
+                "end": span.end,
+            })
+        })
+        .collect();
+
+    write_object(out, "match", data)
+}
+
+pub fn context(out: &mut impl Write, name: &str, line: Line) -> io::Result<()> {
+    let mut data = line_data(name, line);
+    data["submatches"] = json!([]);
+    write_object(out, "context", data)
+}
+
+pub fn end(out: &mut impl Write, name: &str, stats: &Stats, elapsed: Duration) -> io::Result<()> {
+    write_object(out, "end", json!({ "path": text(name), "stats": stats_data(stats, elapsed) }))
+}
+
+pub fn summary(out: &mut impl Write, stats: &Stats, elapsed: Duration) -> io::Result<()> {
+    write_object(
+        out,
+        "summary",
+        json!({ "elapsed_total": elapsed_data(elapsed), "stats": stats_data(stats, elapsed) }),
+    )
+}
+
+fn line_data(name: &str, line: Line) -> Value {
+    json!({
+        "path": text(name),
+        "lines": text(line.text),
+        "line_number": line.number,
+        "absolute_offset": line.offset,
+    })
+}
+
+fn stats_data(stats: &Stats, elapsed: Duration) -> Value {
+    json!({
+        "elapsed": elapsed_data(elapsed),
+        "searches": stats.searches,
+        "searches_with_match": stats.searches_with_match,
+        "matched_lines": stats.matched_lines,
+        "matches": stats.matches,
+    })
+}
+
+fn elapsed_data(elapsed: Duration) -> Value {
+    json!({
+        "secs": elapsed.as_secs(),
+        "nanos": elapsed.subsec_nanos(),
+        "human": format!("{:.6}s", elapsed.as_secs_f64()),
+    })
+}
+
+// ripgrep wraps strings in {"text": ...}, so it can use {"bytes": ...} for invalid UTF-8.
+// We read lines lossily, so we always have text. The "start" and "end" of the submatches are
+// offsets in that text, while "absolute_offset" counts the bytes of the file (see search.rs).
+fn text(text: &str) -> Value {
+    json!({ "text": text })
+}
+
+fn write_object(out: &mut impl Write, kind: &str, data: Value) -> io::Result<()> {
+    serde_json::to_writer(&mut *out, &json!({ "type": kind, "data": data }))?;
+    writeln!(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ops::Range;
+
+    fn parse(out: Vec<u8>) -> Vec<Value> {
+        String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn match_object() {
+        let mut out = Vec::new();
+        let m = Match {
+            line: Line { number: 2, offset: 25, text: "Are you nobody, too?" },
+            spans: vec![Range { start: 16, end: 18 }],
+        };
+        matched(&mut out, "poem.txt", &m).unwrap();
+
+        assert_eq!(
+            vec![json!({
+                "type": "match",
+                "data": {
+                    "path": { "text": "poem.txt" },
+                    "lines": { "text": "Are you nobody, too?" },
+                    "line_number": 2,
+                    "absolute_offset": 25,
+                    "submatches": [{ "match": { "text": "to" }, "start": 16, "end": 18 }],
+                },
+            })],
+            parse(out)
+        );
+    }
+
+    #[test]
+    fn begin_context_end_and_summary() {
+        let mut out = Vec::new();
+        let stats = Stats { searches: 1, searches_with_match: 1, matched_lines: 2, matches: 3, errors: 0 };
+
+        begin(&mut out, "poem.txt").unwrap();
+        context(&mut out, "poem.txt", Line { number: 1, offset: 0, text: "I'm nobody!" }).unwrap();
+        end(&mut out, "poem.txt", &stats, Duration::from_millis(1500)).unwrap();
+        summary(&mut out, &stats, Duration::from_millis(1500)).unwrap();
+
+        let objects = parse(out);
+        let kinds: Vec<&str> = objects.iter().map(|o| o["type"].as_str().unwrap()).collect();
+        assert_eq!(vec!["begin", "context", "end", "summary"], kinds);
+
+        assert_eq!(json!([]), objects[1]["data"]["submatches"]);
+        assert_eq!(json!(3), objects[2]["data"]["stats"]["matches"]);
+        assert_eq!(json!(1), objects[3]["data"]["elapsed_total"]["secs"]);
+        assert_eq!(json!(500_000_000), objects[3]["data"]["elapsed_total"]["nanos"]);
+    }
+}
@@ -1,73 +1,472 @@
-use std::{fs, error::Error};
-use std::env;
+use std::error::Error;
+use std::fmt::Display;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::time::Instant;
 
-// In the success case, we return a unit type () which is nothing,
+pub mod config;
+pub mod decompress;
+pub mod follow;
+pub mod fuzzy;
+pub mod index;
+pub mod json;
+pub mod matcher;
+pub mod output;
+pub mod parallel;
+pub mod replace;
+pub mod search;
+pub mod searcher;
+pub mod walk;
+
+pub use config::{ColorChoice, Config, ConfigError, PatternSyntax, RecordMode, SortOrder};
+pub use fuzzy::FuzzyMatcher;
+pub use json::Stats;
+pub use matcher::{CaseInsensitiveMatcher, LiteralMatcher, Matcher, RegexMatcher};
+pub use output::Printer;
+pub use search::{Entry, Line, Match};
+pub use searcher::{BuildError, Searcher, SearcherBuilder, Sink};
+
+/// Receives the problems that don't stop the search, like a file that can't be read, or a
+/// followed file that was truncated. The library never prints them itself: the minigrep binary
+/// prints them on stderr, unless -s is given. Several threads may search at the same time,
+/// so it has to be Sync.
+pub type Messages<'a> = dyn Fn(&dyn Display) + Sync + 'a;
+
+// In the success case, we return what was found, so main can choose the exit code,
 // and in the case of an error, we return an error.
-pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
-    // If error occurs, this error will automatically be returned.
-    let contents = fs::read_to_string(config.filename)?;
+pub fn run(config: Config, messages: &Messages) -> Result<Stats, Box<dyn Error>> {
+    // We lock stdout once, and buffer the output, instead of locking it for every println!().
+    let stdout = io::stdout();
+    let mut out = BufWriter::new(stdout.lock());
+
+    let stats = run_to(config, &mut out, messages)?;
+
+    out.flush()?;
+    Ok(stats)
+}
+
+/// Does the same as run(), but writes the output to `out` instead of stdout.
+///
+/// This is what the minigrep binary does with the command line. To search from another
+/// program without a Config, use Searcher::builder() instead.
+///
+/// An error that only affects one file does not stop the search. It is given to `messages`,
+/// and counted in Stats::errors. Only other errors are returned.
+pub fn run_to(config: Config, out: &mut impl Write, messages: &Messages) -> Result<Stats, Box<dyn Error>> {
+    let start = Instant::now();
+
+    // We build the searcher first, so an invalid regex is reported before we read the file.
+    let searcher = config.searcher()?;
+
+    // Just like grep, we only say which file a line came from, when searching several files.
+    let show_path = config.recursive || config.paths.len() > 1;
+    let printer = Printer::new(&config, show_path);
 
-    let results = if config.case_sensitive {
-        search(&config.query, &contents)
+    // A followed file never ends, so there is nothing else to search, and nothing to sum up.
+    if config.follow {
+        return follow_path(&config, &searcher, Path::new(&config.paths[0]), &printer, out, messages);
+    }
+
+    let paths = walk::paths(&config)?;
+    let paths = match config.index {
+        true => index::candidates(&config, paths, messages)?,
+        false => paths,
+    };
+
+    // --in-place and --dry-run change (or would change) the files, instead of searching them.
+    if config.in_place || config.dry_run {
+        let mut stats = Stats::default();
+        for path in paths {
+            stats += match path {
+                Ok(path) => match replace::rewrite(&config, searcher.matcher(), &path, out) {
+                    Ok(changed) => Stats {
+                        searches: 1,
+                        searches_with_match: usize::from(changed > 0),
+                        matched_lines: changed,
+                        ..Stats::default()
+                    },
+                    Err(err) => file_error(messages, &format!("{}: {}", path.display(), err)),
+                },
+                Err(err) => file_error(messages, &err),
+            };
+        }
+        return Ok(stats);
+    }
+
+    // Searching files in parallel only pays off when there can be more than one file.
+    // With -q, we stop at the first match, so then we search one file after the other.
+    let threads = config.threads();
+    let stats = if sort_by_score(&config) {
+        search_by_score(&searcher, paths, &printer, out, messages)?
+    } else if threads > 1 && show_path && !config.quiet {
+        parallel::search_paths(&config, &searcher, paths, threads, &printer, out, messages)?
     } else {
-        search_case_insensitive(&config.query, &contents)
+        let mut printed_group = false;
+        let mut stats = Stats::default();
+
+        for path in paths {
+            match path {
+                Ok(path) => stats += search_path(&config, &searcher, &path, &printer, out, &mut printed_group, messages)?,
+                Err(err) => stats += file_error(messages, &err),
+            }
+
+            if config.quiet && stats.matched_lines > 0 {
+                break;
+            }
+        }
+
+        stats
     };
 
-    for line in results {
-        println!("{}", line);
+    if config.json && !config.quiet {
+        json::summary(out, &stats, start.elapsed())?;
+    }
+
+    Ok(stats)
+}
+
+// Something went wrong while searching one file.
+enum FileError {
+    /// The file could not be read (or decompressed). We only skip this file.
+    Read(io::Error),
+    /// The output could not be written, so there is no point in searching the other files.
+    Write(io::Error),
+    /// Not really an error: with -q, we stop reading at the first match.
+    Found,
+}
+
+impl From<io::Error> for FileError {
+    fn from(err: io::Error) -> FileError {
+        FileError::Read(err)
+    }
+}
+
+// Reports an error that only affects one file, so we can go on with the next one.
+// Returns the stats of that file: nothing was found, but there was an error.
+fn file_error(messages: &Messages, err: &dyn Display) -> Stats {
+    messages(err);
+    Stats { errors: 1, ..Stats::default() }
+}
+
+// Searches one file, and prints the result the way the config asks for. "-" means standard input,
+// so minigrep can be used in a pipeline. If the file can't be read, the error is reported, and
+// we return no results for it. Only an error while writing the output is returned.
+fn search_path(
+    config: &Config,
+    searcher: &Searcher,
+    path: &Path,
+    printer: &Printer,
+    out: &mut impl Write,
+    printed_group: &mut bool,
+    messages: &Messages,
+) -> io::Result<Stats> {
+    let name = input_name(path);
+    let mut sink = PrintSink::new(config, searcher.matcher(), printer, &name, out, printed_group);
+    let result = search_input(searcher, path, &mut sink);
+
+    match result {
+        Ok(_) | Err(FileError::Found) => Ok(sink.stats),
+        Err(FileError::Read(err)) => Ok(file_error(messages, &format!("{}: {}", name, err))),
+        Err(FileError::Write(err)) => Err(err),
+    }
+}
+
+// "-" is standard input, just like in grep.
+fn search_input<S: Sink>(searcher: &Searcher, path: &Path, sink: &mut S) -> Result<usize, S::Error> {
+    match path == Path::new("-") {
+        true => searcher.search_reader(io::stdin().lock(), sink),
+        false => searcher.search_path(path, sink),
+    }
+}
+
+fn input_name(path: &Path) -> String {
+    match path == Path::new("-") {
+        true => "(standard input)".to_string(),
+        false => path.display().to_string(),
+    }
+}
+
+// Searches the file of --follow, and then keeps searching what is added to it. This only returns
+// when something goes wrong, so unlike in search_path(), an error while reading is returned too.
+fn follow_path(
+    config: &Config,
+    searcher: &Searcher,
+    path: &Path,
+    printer: &Printer,
+    out: &mut impl Write,
+    messages: &Messages,
+) -> Result<Stats, Box<dyn Error>> {
+    let name = path.display().to_string();
+    let reader = follow::FollowReader::open(path, messages).map_err(|err| format!("{}: {}", name, err))?;
+
+    let mut printed_group = false;
+    let mut sink = PrintSink::new(config, searcher.matcher(), printer, &name, out, &mut printed_group);
+    match searcher.search_reader(BufReader::new(reader), &mut sink) {
+        Ok(_) | Err(FileError::Found) => Ok(sink.stats),
+        Err(FileError::Read(err)) => Err(format!("{}: {}", name, err).into()),
+        Err(FileError::Write(err)) => Err(err.into()),
+    }
+}
+
+// With -l and -c, we only need to know how many lines were selected, and with -q only whether
+// there are any. --json always prints the lines, so it wins over -l and -c, but not over -q.
+fn only_count(config: &Config) -> bool {
+    config.quiet || (!config.json && (config.files_with_matches || config.count))
+}
+
+// --sort=score only changes the order of the lines we print,
+// so it does nothing with -l, -c, -q or --json.
+fn sort_by_score(config: &Config) -> bool {
+    config.sort == SortOrder::Score && !config.json && !only_count(config)
+}
+
+// How many lines of context to print before and after every match.
+// With -l and -c we only count, so there is never any context.
+// Neither is there after sorting, because the lines around a match are not next to it anymore.
+fn context_lines(config: &Config) -> (usize, usize) {
+    match only_count(config) || sort_by_score(config) {
+        true => (0, 0),
+        false => (config.lines_before(), config.lines_after()),
+    }
+}
+
+// The Sink of the minigrep binary: prints the results of one file, the way the config asks for.
+// It also counts what it has seen, for the --json summary.
+struct PrintSink<'a, W: Write> {
+    config: &'a Config,
+    matcher: &'a dyn Matcher,
+    printer: &'a Printer,
+    name: &'a str,
+    out: &'a mut W,
+    printed_group: &'a mut bool,
+    first_entry: bool,
+    matches: usize,
+    start: Instant,
+    stats: Stats,
+}
+
+impl<'a, W: Write> PrintSink<'a, W> {
+    fn new(
+        config: &'a Config,
+        matcher: &'a dyn Matcher,
+        printer: &'a Printer,
+        name: &'a str,
+        out: &'a mut W,
+        printed_group: &'a mut bool,
+    ) -> PrintSink<'a, W> {
+        PrintSink {
+            config,
+            matcher,
+            printer,
+            name,
+            out,
+            printed_group,
+            first_entry: true,
+            matches: 0,
+            start: Instant::now(),
+            stats: Stats::default(),
+        }
+    }
+
+    // Whether lines are printed as plain text. With --json they are printed as JSON,
+    // and with -l and -c they are not printed at all.
+    fn prints_lines(&self) -> bool {
+        !self.config.json && !only_count(self.config)
+    }
+
+    // Whether lines are printed as JSON.
+    fn prints_json(&self) -> bool {
+        self.config.json && !self.config.quiet
+    }
+
+    // Called before printing the first line of this file.
+    fn start_group(&mut self) -> io::Result<()> {
+        // Just like grep, groups from different files are also separated by "--".
+        let (before, after) = context_lines(self.config);
+        if self.first_entry && (before > 0 || after > 0) && *self.printed_group {
+            self.printer.separator(self.out)?;
+        }
+        self.first_entry = false;
+        *self.printed_group = true;
+        Ok(())
+    }
+
+    fn print_match(&mut self, m: &Match) -> io::Result<()> {
+        if self.prints_json() {
+            return json::matched(self.out, self.name, m);
+        }
+        if !self.prints_lines() {
+            return Ok(());
+        }
+
+        self.start_group()?;
+        match &self.config.replace {
+            // With --replace, we print the line as it would be after the replacement.
+            Some(replacement) => {
+                let (text, spans) = self.matcher.replace_all(m.line.text, replacement);
+                self.printer.line(self.out, self.name, Line { text: &text, ..m.line }, &spans, ':')
+            }
+            None => self.printer.line(self.out, self.name, m.line, &m.spans, ':'),
+        }
+    }
+
+    fn print_context(&mut self, line: &Line) -> io::Result<()> {
+        if self.prints_json() {
+            return json::context(self.out, self.name, *line);
+        }
+        if !self.prints_lines() {
+            return Ok(());
+        }
+
+        self.start_group()?;
+        self.printer.line(self.out, self.name, *line, &[], '-')
     }
 
-    Ok(())
+    // With --follow, a line should show up as soon as it is written to the file,
+    // and not only once the output buffer is full.
+    fn flush_when_following(&mut self) -> io::Result<()> {
+        match self.config.follow {
+            true => self.out.flush(),
+            false => Ok(()),
+        }
+    }
+
+    fn print_summary(&mut self, selected: usize) -> io::Result<()> {
+        self.stats = Stats {
+            searches: 1,
+            searches_with_match: usize::from(selected > 0),
+            matched_lines: selected,
+            matches: self.matches,
+            errors: 0,
+        };
+
+        if self.config.quiet {
+            Ok(())
+        } else if self.config.json {
+            json::end(self.out, self.name, &self.stats, self.start.elapsed())
+        } else if self.config.files_with_matches && selected > 0 {
+            self.printer.path(self.out, self.name)
+        } else if self.config.count && !self.config.files_with_matches {
+            self.printer.count(self.out, self.name, selected)
+        } else {
+            Ok(())
+        }
+    }
 }
 
-pub struct Config {
-    pub query: String,
-    pub filename: String,
-    pub case_sensitive: bool,
+impl<W: Write> Sink for PrintSink<'_, W> {
+    type Error = FileError;
+
+    fn matched(&mut self, m: &Match) -> Result<(), FileError> {
+        self.matches += m.spans.len();
+
+        // With -q, the first match is all we need to know, so we don't read any further.
+        if self.config.quiet {
+            self.print_summary(1).map_err(FileError::Write)?;
+            return Err(FileError::Found);
+        }
+
+        self.print_match(m).and_then(|_| self.flush_when_following()).map_err(FileError::Write)
+    }
+
+    fn context(&mut self, line: &Line) -> Result<(), FileError> {
+        self.print_context(line).and_then(|_| self.flush_when_following()).map_err(FileError::Write)
+    }
+
+    fn separator(&mut self) -> Result<(), FileError> {
+        match self.prints_lines() {
+            true => self.printer.separator(self.out).map_err(FileError::Write),
+            false => Ok(()),
+        }
+    }
+
+    fn begin(&mut self) -> Result<(), FileError> {
+        match self.prints_json() {
+            true => json::begin(self.out, self.name).map_err(FileError::Write),
+            false => Ok(()),
+        }
+    }
+
+    fn finish(&mut self, selected: usize) -> Result<(), FileError> {
+        self.print_summary(selected).map_err(FileError::Write)
+    }
 }
 
-impl Config {
-    pub fn new(mut args: env::Args) -> Result<Config, &'static str> {
-        args.next();
+// With --sort=score, we can only print once we have seen every line of every file,
+// so we collect all matching lines first, and then print them sorted by their distance.
+fn search_by_score(
+    searcher: &Searcher,
+    paths: walk::Paths,
+    printer: &Printer,
+    out: &mut impl Write,
+    messages: &Messages,
+) -> Result<Stats, Box<dyn Error>> {
+    let matcher = searcher.matcher();
+    let mut found = Vec::new();
+    let mut stats = Stats::default();
 
-        // We take ownership of the strings.
-        let query = match args.next() {
-            Some(arg) => arg,
-            None => return Err("Didn't get a query string"),
+    for path in paths {
+        let path = match path {
+            Ok(path) => path,
+            Err(err) => {
+                stats += file_error(messages, &err);
+                continue;
+            }
         };
 
-        let filename = match args.next() {
-            Some(arg) => arg,
-            None => return Err("Didn't get a filename string"),
+        // Nothing is printed yet, so every error here is an error while reading.
+        let name = input_name(&path);
+        let mut matches = 0;
+        let result = search_input(searcher, &path, &mut |m: &Match| {
+            let distance = matcher.distance(m.line.text);
+            matches += m.spans.len();
+            found.push((distance, name.clone(), m.line.number, m.line.offset, m.line.text.to_string(), m.spans.clone()));
+            Ok(())
+        });
+
+        stats += match result {
+            Ok(selected) => Stats {
+                searches: 1,
+                searches_with_match: usize::from(selected > 0),
+                matched_lines: selected,
+                matches,
+                errors: 0,
+            },
+            Err(err) => file_error(messages, &format!("{}: {}", name, err)),
         };
+    }
 
-        let case_sensitive = env::var("CASE_INSENSITIVE").is_err();
+    // sort_by_key is stable, so lines with the same distance keep their order.
+    found.sort_by_key(|&(distance, ..)| distance);
 
-        // And here, Config take ownership of the strings.
-        Ok(Config { query, filename, case_sensitive })
+    for (_, name, number, offset, text, spans) in &found {
+        let line = Line { number: *number, offset: *offset, text };
+        printer.line(out, name, line, spans, ':')?;
     }
+
+    Ok(stats)
 }
 
-pub fn search<'a>(query: &str, content: &'a str) -> Vec<&'a str> {
+/// Returns every line of `content` that the matcher matches.
+pub fn search_with<'a>(matcher: &dyn Matcher, content: &'a str) -> Vec<&'a str> {
     content
         .lines()
-        .filter(|line| line.contains(query))
+        .filter(|line| matcher.is_match(line))
         .collect()
 }
 
-pub fn search_case_insensitive<'a>(query: &str, content: &'a str) -> Vec<&'a str> {
-    let query = query.to_lowercase();
-    let mut results: Vec<&str> = Vec::new();
-
-    for line in content.lines() {
-        if line.to_lowercase().contains(&query) {
-            results.push(line);
-        }
-    }
+pub fn search<'a>(query: &str, content: &'a str) -> Vec<&'a str> {
+    search_with(&LiteralMatcher::new(&[query.to_string()]), content)
+}
 
-    results
+pub fn search_case_insensitive<'a>(query: &str, content: &'a str) -> Vec<&'a str> {
+    search_with(&CaseInsensitiveMatcher::new(&[query.to_string()]), content)
 }
 
+// For the tests that don't care about the messages.
+#[cfg(test)]
+pub(crate) fn no_messages(_: &dyn Display) {}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -95,4 +494,114 @@ Trust me.";
 
         assert_eq!(vec!["Rust:", "Trust me."], search_case_insensitive(query, contents));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn regex_with_several_patterns() {
+        let patterns = vec![r"^\w+:$".to_string(), "th?ree".to_string()];
+        let matcher = RegexMatcher::new(&patterns, false).unwrap();
+        let contents = "\
+Rust:
+safe, fast, productive.
+Pick three.
+Duct tape.";
+
+        assert_eq!(vec!["Rust:", "Pick three."], search_with(&matcher, contents));
+    }
+
+    #[test]
+    fn json_lines_for_every_file_and_a_summary() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("poem.txt");
+        std::fs::write(&path, "I'm nobody!\nAre you nobody, too?\n").unwrap();
+
+        let args = ["minigrep", "--json", "-c", "nobody", path.to_str().unwrap()];
+        let config = Config::new(args.iter().map(|arg| arg.to_string())).unwrap();
+        let mut out = Vec::new();
+        run_to(config, &mut out, &no_messages).unwrap();
+
+        let objects: Vec<serde_json::Value> = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let kinds: Vec<&str> = objects.iter().map(|o| o["type"].as_str().unwrap()).collect();
+
+        // --json wins over -c, so the matches are still printed.
+        assert_eq!(vec!["begin", "match", "match", "end", "summary"], kinds);
+        assert_eq!(2, objects[4]["data"]["stats"]["matched_lines"]);
+        assert_eq!(1, objects[4]["data"]["stats"]["searches_with_match"]);
+    }
+
+    #[test]
+    fn fuzzy_matches_sorted_by_score() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.log");
+        std::fs::write(&path, "conecton lost\nconnection lost\nconection lost\nall good\n").unwrap();
+
+        let args = ["minigrep", "--fuzzy", "2", "--sort=score", "connection", path.to_str().unwrap()];
+        let config = Config::new(args.iter().map(|arg| arg.to_string())).unwrap();
+        let mut out = Vec::new();
+        run_to(config, &mut out, &no_messages).unwrap();
+
+        assert_eq!("connection lost\nconection lost\nconecton lost\n", String::from_utf8(out).unwrap());
+    }
+
+    #[test]
+    fn compressed_files_and_per_file_errors() {
+        use std::io::Write;
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(b"old: connection refused\n").unwrap();
+        std::fs::write(dir.path().join("app.log.1.gz"), encoder.finish().unwrap()).unwrap();
+        std::fs::write(dir.path().join("app.log.2.gz"), b"\x1f\x8b broken").unwrap();
+        std::fs::write(dir.path().join("app.log"), "new: connection refused\n").unwrap();
+
+        let root = dir.path().to_str().unwrap();
+        let args = ["minigrep", "-z", "-r", "-j1", "--no-ignore", "refused", root];
+        let config = Config::new(args.iter().map(|arg| arg.to_string())).unwrap();
+        let mut out = Vec::new();
+        let messages = std::sync::Mutex::new(Vec::new());
+        let stats = run_to(config, &mut out, &|err: &dyn Display| messages.lock().unwrap().push(err.to_string())).unwrap();
+
+        // The broken file is given to the messages, and the other files are still searched.
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().map(|line| line.rsplit_once('/').unwrap().1).collect();
+        assert_eq!(vec!["app.log:new: connection refused", "app.log.1.gz:old: connection refused"], lines);
+
+        let messages = messages.into_inner().unwrap();
+        assert_eq!(1, stats.errors);
+        assert_eq!(1, messages.len());
+        assert!(messages[0].contains("app.log.2.gz: "), "{:?}", messages);
+    }
+
+    #[test]
+    fn stats_for_the_exit_code() {
+        let dir = tempfile::tempdir().unwrap();
+        let poem = dir.path().join("poem.txt");
+        std::fs::write(&poem, "I'm nobody! Who are you?\nAre you nobody, too?\n").unwrap();
+        let poem = poem.to_str().unwrap();
+        let missing = dir.path().join("missing.txt");
+        let missing = missing.to_str().unwrap();
+
+        let run = |args: &[&str]| {
+            let config = config::parse(args).unwrap();
+            let mut out = Vec::new();
+            let stats = run_to(config, &mut out, &no_messages).unwrap();
+            (stats, String::from_utf8(out).unwrap())
+        };
+
+        // A file that can't be read does not stop the search of the other files.
+        let (stats, out) = run(&["-s", "nobody", missing, poem]);
+        assert_eq!((2, 1, 1), (stats.matched_lines, stats.searches_with_match, stats.errors));
+        assert_eq!(2, out.lines().count());
+
+        // -q prints nothing, and stops at the first match.
+        let (stats, out) = run(&["-q", "nobody", poem, poem]);
+        assert_eq!((1, 1, 0), (stats.matched_lines, stats.searches, stats.errors));
+        assert_eq!("", out);
+
+        let (stats, _) = run(&["-q", "somebody", poem]);
+        assert_eq!(0, stats.matched_lines);
+    }
+}
@@ -2,21 +2,85 @@
 // our binary crate call functions in the library crate.
 
 use std::env;
+use std::fmt::Display;
+use std::path::Path;
 use std::process;
 
-use minigrep::Config;
+// Just like grep: 0 if a line was selected, 1 if none was, and 2 if something went wrong.
+const MATCH: i32 = 0;
+const NO_MATCH: i32 = 1;
+const ERROR: i32 = 2;
+
+use minigrep::index::Index;
+use minigrep::{config, Config, ConfigError};
 
 fn main() {
-    let config = Config::new(env::args()).unwrap_or_else(|err| {
+    // `minigrep index DIR` builds the index, instead of searching.
+    // To search for the word "index", use `minigrep -e index`.
+    let args: Vec<String> = env::args().collect();
+    if args.get(1).map(String::as_str) == Some("index") {
+        let dir = args.get(2).map(String::as_str).unwrap_or(".");
+        build_index(Path::new(dir));
+        return;
+    }
+
+    let config = Config::new(args.into_iter()).unwrap_or_else(|err| {
+        // --help is not really an error, so we print the help and exit successfully.
+        if err == ConfigError::HelpRequested {
+            print!("{}", config::help());
+            process::exit(MATCH);
+        }
+
         eprintln!("Problem parsing arguments: {}", err);
-        process::exit(1);
+        eprintln!("Try 'minigrep --help' for more information.");
+        process::exit(ERROR);
     });
 
-    println!("Searching for '{}'", config.query);
-    println!("In file '{}':\n", config.filename);
+    // Problems with one file don't stop the search. The library gives them to us,
+    // and we print them, unless -s says not to.
+    let no_messages = config.no_messages;
+    let messages = |err: &dyn Display| {
+        if !no_messages {
+            report(err);
+        }
+    };
 
-    if let Err(e) = minigrep::run(config) {
+    let quiet = config.quiet;
+    let stats = minigrep::run(config, &messages).unwrap_or_else(|e| {
         eprintln!("Application error: {}", e);
-        process::exit(1);
+        process::exit(ERROR);
+    });
+
+    // With -q, a match is all that was asked for, so it wins over errors in other files.
+    let code = if quiet && stats.matched_lines > 0 {
+        MATCH
+    } else if stats.errors > 0 {
+        ERROR
+    } else if stats.matched_lines > 0 {
+        MATCH
+    } else {
+        NO_MATCH
+    };
+    process::exit(code);
+}
+
+fn build_index(dir: &Path) {
+    match Index::update(dir, &report) {
+        Ok(summary) => println!(
+            "Indexed {} files in '{}' ({} new or changed, {} removed)",
+            summary.files,
+            dir.display(),
+            summary.updated,
+            summary.removed
+        ),
+        Err(e) => {
+            eprintln!("Application error: {}", e);
+            process::exit(ERROR);
+        }
     }
 }
+
+// Tells the user about something that went wrong, without stopping the search.
+fn report(err: &dyn Display) {
+    eprintln!("minigrep: {}", err);
+}
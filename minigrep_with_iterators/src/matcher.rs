@@ -0,0 +1,354 @@
+// A Matcher knows how to find a pattern inside of a single line.
+//
+// By putting the different kinds of matching (plain text, case-insensitive text and regex)
+// behind one trait, search() does not need to care which one it is using. It just asks
+// the matcher where the next match is.
+
+use std::ops::Range;
+
+use regex::{Regex, RegexBuilder};
+
+use crate::search::find_spans;
+
+// Matchers are Send + Sync, so several threads can search with the same matcher at once.
+pub trait Matcher: Send + Sync {
+    /// Returns the byte range of the first match in `line`, that starts at or after `start`.
+    fn find_at(&self, line: &str, start: usize) -> Option<Range<usize>>;
+
+    fn is_match(&self, line: &str) -> bool {
+        self.find_at(line, 0).is_some()
+    }
+
+    /// How far the best match in `line` is from the pattern, for --sort=score. Lower is better.
+    /// Only --fuzzy finds matches that are not exact, so by default this is 0.
+    fn distance(&self, _line: &str) -> usize {
+        0
+    }
+
+    /// Replaces every match in `line` with `replacement` (for --replace). Returns the new line,
+    /// and where the replacements are in it, so they can be highlighted.
+    ///
+    /// Only regular expressions have capture groups, so by default `replacement` is used as it is.
+    fn replace_all(&self, line: &str, replacement: &str) -> (String, Vec<Range<usize>>) {
+        let mut replaced = String::with_capacity(line.len());
+        let mut spans = Vec::new();
+        let mut last = 0;
+
+        for span in find_spans(self, line) {
+            replaced.push_str(&line[last..span.start]);
+            spans.push(replaced.len()..replaced.len() + replacement.len());
+            replaced.push_str(replacement);
+            last = span.end;
+        }
+
+        replaced.push_str(&line[last..]);
+        (replaced, spans)
+    }
+}
+
+/// Matches one or more fixed strings (-F). A line matches if it contains any of them.
+pub struct LiteralMatcher {
+    patterns: Vec<String>,
+}
+
+impl LiteralMatcher {
+    pub fn new(patterns: &[String]) -> LiteralMatcher {
+        LiteralMatcher { patterns: patterns.to_vec() }
+    }
+}
+
+impl Matcher for LiteralMatcher {
+    fn find_at(&self, line: &str, start: usize) -> Option<Range<usize>> {
+        // The leftmost match wins, and if two patterns start at the same place, the longest one.
+        self.patterns
+            .iter()
+            .filter_map(|pattern| {
+                line[start..]
+                    .find(pattern.as_str())
+                    .map(|i| start + i..start + i + pattern.len())
+            })
+            .min_by_key(|range| (range.start, usize::MAX - range.end))
+    }
+}
+
+/// Matches one or more fixed strings, ignoring case.
+///
+/// Characters are compared with Unicode simple case folding, which maps every character to
+/// exactly one other character: 'ẞ' and 'ß' are the same, and so are 'Σ', 'σ' and 'ς'.
+/// Because one character always folds to one character, a match can't end in the middle
+/// of a character, and the returned range points into the original line.
+///
+/// Simple folding does not turn 'ß' into "ss" (that is full case folding, which changes the
+/// length of the text), so "STRASSE" does not match "straße". The regex engine (-E -i) folds
+/// the same way, so both engines always agree. There are no language specific rules either:
+/// 'I' matches 'i', but the Turkish 'ı' and 'İ' only match themselves.
+pub struct CaseInsensitiveMatcher {
+    patterns: Vec<Vec<char>>,
+}
+
+impl CaseInsensitiveMatcher {
+    pub fn new(patterns: &[String]) -> CaseInsensitiveMatcher {
+        CaseInsensitiveMatcher {
+            patterns: patterns.iter().map(|pattern| pattern.chars().map(fold).collect()).collect(),
+        }
+    }
+}
+
+impl Matcher for CaseInsensitiveMatcher {
+    fn find_at(&self, line: &str, start: usize) -> Option<Range<usize>> {
+        line[start..]
+            .char_indices()
+            .map(|(i, _)| start + i)
+            .chain(std::iter::once(line.len()))
+            .find_map(|i| {
+                self.patterns
+                    .iter()
+                    .filter_map(|pattern| folded_prefix_len(&line[i..], pattern))
+                    .max()
+                    .map(|len| i..i + len)
+            })
+    }
+}
+
+/// Returns the simple case folding of `c`, or `c` itself if it has none.
+pub fn fold(c: char) -> char {
+    unicode_case_mapping::case_folded(c)
+        .and_then(|folded| char::from_u32(folded.get()))
+        .unwrap_or(c)
+}
+
+// If the folded `text` starts with `pattern`, returns how many bytes of `text` that covers.
+fn folded_prefix_len(text: &str, pattern: &[char]) -> Option<usize> {
+    let mut chars = text.char_indices();
+
+    for &want in pattern {
+        match chars.next() {
+            Some((_, c)) if fold(c) == want => {}
+            _ => return None,
+        }
+    }
+
+    Some(chars.next().map_or(text.len(), |(offset, _)| offset))
+}
+
+/// Matches one or more regular expressions (-E). Several patterns are combined with OR.
+pub struct RegexMatcher {
+    regex: Regex,
+}
+
+impl RegexMatcher {
+    pub fn new(patterns: &[String], case_insensitive: bool) -> Result<RegexMatcher, regex::Error> {
+        RegexMatcher::build(patterns, case_insensitive, false)
+    }
+
+    /// For text of more than one line (-U and --paragraph): ^ and $ match at the start
+    /// and the end of every line, instead of only at the start and the end of the text.
+    pub fn multi_line(patterns: &[String], case_insensitive: bool) -> Result<RegexMatcher, regex::Error> {
+        RegexMatcher::build(patterns, case_insensitive, true)
+    }
+
+    fn build(patterns: &[String], case_insensitive: bool, multi_line: bool) -> Result<RegexMatcher, regex::Error> {
+        // (?:...) is a group that does not capture, so "a|b" and "c" become "(?:a|b)|(?:c)".
+        // Because all patterns end up in one regex, their capture groups are numbered one after
+        // the other: with -e '(a)' -e '(b)', the group of the second pattern is $2, not $1.
+        // A group of a pattern that did not match is empty in the replacement.
+        let combined = patterns
+            .iter()
+            .map(|pattern| format!("(?:{})", pattern))
+            .collect::<Vec<_>>()
+            .join("|");
+
+        let regex = RegexBuilder::new(&combined)
+            .case_insensitive(case_insensitive)
+            .multi_line(multi_line)
+            .build()?;
+
+        Ok(RegexMatcher { regex })
+    }
+}
+
+impl Matcher for RegexMatcher {
+    fn find_at(&self, line: &str, start: usize) -> Option<Range<usize>> {
+        self.regex.find_at(line, start).map(|m| m.range())
+    }
+
+    // $1, ${1} or ${name} in the replacement is replaced with what that group matched.
+    fn replace_all(&self, line: &str, replacement: &str) -> (String, Vec<Range<usize>>) {
+        let mut replaced = String::with_capacity(line.len());
+        let mut spans = Vec::new();
+        let mut last = 0;
+
+        for captures in self.regex.captures_iter(line) {
+            let whole = captures.get(0).unwrap();
+            replaced.push_str(&line[last..whole.start()]);
+
+            let start = replaced.len();
+            captures.expand(replacement, &mut replaced);
+            spans.push(start..replaced.len());
+            last = whole.end();
+        }
+
+        replaced.push_str(&line[last..]);
+        (replaced, spans)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn patterns(patterns: &[&str]) -> Vec<String> {
+        patterns.iter().map(|p| p.to_string()).collect()
+    }
+
+    // Every engine has to pass the same tests for plain text patterns,
+    // so we run them against all of them (with regex special characters escaped).
+    fn case_sensitive_matchers(queries: &[&str]) -> Vec<Box<dyn Matcher>> {
+        let escaped: Vec<String> = queries.iter().map(|q| regex::escape(q)).collect();
+        vec![
+            Box::new(LiteralMatcher::new(&patterns(queries))),
+            Box::new(RegexMatcher::new(&escaped, false).unwrap()),
+        ]
+    }
+
+    fn case_insensitive_matchers(queries: &[&str]) -> Vec<Box<dyn Matcher>> {
+        let escaped: Vec<String> = queries.iter().map(|q| regex::escape(q)).collect();
+        vec![
+            Box::new(CaseInsensitiveMatcher::new(&patterns(queries))),
+            Box::new(RegexMatcher::new(&escaped, true).unwrap()),
+        ]
+    }
+
+    #[test]
+    fn finds_first_match() {
+        for matcher in case_sensitive_matchers(&["duct"]) {
+            assert_eq!(Some(15..19), matcher.find_at("safe, fast, productive.", 0));
+            assert_eq!(None, matcher.find_at("Duct tape.", 0));
+        }
+    }
+
+    #[test]
+    fn finds_match_after_start() {
+        for matcher in case_sensitive_matchers(&["ab"]) {
+            assert_eq!(Some(0..2), matcher.find_at("ab ab", 0));
+            assert_eq!(Some(3..5), matcher.find_at("ab ab", 1));
+            assert_eq!(None, matcher.find_at("ab ab", 4));
+        }
+    }
+
+    #[test]
+    fn several_patterns_are_combined_with_or() {
+        for matcher in case_sensitive_matchers(&["tape", "fast"]) {
+            assert!(matcher.is_match("Duct tape."));
+            assert!(matcher.is_match("safe, fast, productive."));
+            assert!(!matcher.is_match("Pick three."));
+            // The leftmost match wins, no matter the order of the patterns.
+            assert_eq!(Some(6..10), matcher.find_at("safe, fast, tape", 0));
+        }
+    }
+
+    #[test]
+    fn special_characters_are_literal() {
+        for matcher in case_sensitive_matchers(&["a.c"]) {
+            assert!(matcher.is_match("a.c"));
+            assert!(!matcher.is_match("abc"));
+        }
+    }
+
+    #[test]
+    fn ignores_case() {
+        for matcher in case_insensitive_matchers(&["rUsT"]) {
+            assert_eq!(Some(0..4), matcher.find_at("Rust:", 0));
+            assert_eq!(Some(1..5), matcher.find_at("Trust me.", 0));
+            assert_eq!(None, matcher.find_at("Pick three.", 0));
+        }
+    }
+
+    #[test]
+    fn case_insensitive_offsets_point_into_the_original_line() {
+        for matcher in case_insensitive_matchers(&["ümlaut"]) {
+            assert_eq!(Some(4..11), matcher.find_at("Das ÜMLAUT", 0));
+        }
+    }
+
+    #[test]
+    fn unicode_case_folding() {
+        for matcher in case_insensitive_matchers(&["STRAẞE"]) {
+            assert_eq!(Some(4..11), matcher.find_at("Die straße", 0));
+            // Only full case folding turns ß into ss.
+            assert!(!matcher.is_match("Die strasse"));
+        }
+
+        // Σ folds to σ, and so does the final ς.
+        for matcher in case_insensitive_matchers(&["ΣΟΦΌΣ"]) {
+            assert_eq!(Some(3..13), matcher.find_at("ο σοφός", 0));
+        }
+
+        // Without Turkish rules, I is i, and the dotted and dotless i are different letters.
+        for matcher in case_insensitive_matchers(&["istanbul"]) {
+            assert!(matcher.is_match("ISTANBUL"));
+            assert!(!matcher.is_match("İSTANBUL"));
+            assert!(!matcher.is_match("ıstanbul"));
+        }
+        for matcher in case_insensitive_matchers(&["İSTANBUL"]) {
+            assert_eq!(Some(3..12), matcher.find_at("in İstanbul", 0));
+        }
+    }
+
+    #[test]
+    fn case_folded_spans_can_be_sliced() {
+        // 'K' (Kelvin sign) is 3 bytes, and folds to the 1 byte 'k'.
+        for matcher in case_insensitive_matchers(&["k"]) {
+            let line = "5 \u{212A} and k";
+            let spans = crate::search::find_spans(matcher.as_ref(), line);
+            let found: Vec<&str> = spans.into_iter().map(|span| &line[span]).collect();
+            assert_eq!(vec!["\u{212A}", "k"], found);
+        }
+    }
+
+    #[test]
+    fn regex_patterns() {
+        let matcher = RegexMatcher::new(&patterns(&["^Pick", r"t\w+e"]), false).unwrap();
+        assert!(matcher.is_match("Pick three."));
+        assert!(matcher.is_match("Duct tape."));
+        assert!(!matcher.is_match("Rust:"));
+    }
+
+    #[test]
+    fn replaces_every_match() {
+        for matcher in case_sensitive_matchers(&["to"]) {
+            let (replaced, spans) = matcher.replace_all("to be or not to be", "TO");
+            assert_eq!("TO be or not TO be", replaced);
+            assert_eq!(vec![0..2, 13..15], spans);
+        }
+
+        // Without a regex, $1 is just text.
+        let (replaced, _) = LiteralMatcher::new(&patterns(&["be"])).replace_all("to be", "$1");
+        assert_eq!("to $1", replaced);
+    }
+
+    #[test]
+    fn replaces_with_capture_groups() {
+        let matcher = RegexMatcher::new(&patterns(&[r"(\w+)@(\w+)"]), false).unwrap();
+        let (replaced, spans) = matcher.replace_all("mail bob@home now", "${2}:$1");
+        assert_eq!("mail home:bob now", replaced);
+        assert_eq!(vec![Range { start: 5, end: 13 }], spans);
+    }
+
+    #[test]
+    fn capture_groups_are_numbered_across_patterns() {
+        let matcher = RegexMatcher::new(&patterns(&[r"(\d+) apples", r"(\d+) pears"]), false).unwrap();
+        let (replaced, _) = matcher.replace_all("3 apples, 4 pears", "[$1|$2]");
+        assert_eq!("[3|], [|4]", replaced);
+
+        // Named groups don't get renumbered, but a name can only be used once in all patterns together.
+        let matcher = RegexMatcher::new(&patterns(&[r"(?<apples>\d+) apples", r"(?<pears>\d+) pears"]), false).unwrap();
+        assert_eq!("[3], [4]", matcher.replace_all("3 apples, 4 pears", "[$apples$pears]").0);
+        assert!(RegexMatcher::new(&patterns(&[r"(?<n>\d+) apples", r"(?<n>\d+) pears"]), false).is_err());
+    }
+
+    #[test]
+    fn invalid_regex_is_an_error() {
+        assert!(RegexMatcher::new(&patterns(&["(unclosed"]), false).is_err());
+    }
+}
@@ -0,0 +1,187 @@
+// How the results are printed, with or without colors.
+//
+// The colors are the same as grep uses: file names are magenta, line numbers are green,
+// separators are cyan, and every match inside of a line is bold red.
+// Whether we use colors at all is decided once, from --color (see Config::use_color()), and
+// kept in the Printer. We write the ANSI escape codes ourselves: a crate like 'colored' decides
+// for the whole process whether to print colors, and then two Printers could not differ.
+
+use std::io::{self, Write};
+use std::ops::Range;
+
+use crate::{Config, Line, RecordMode};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Printer {
+    pub color: bool,
+    /// Prefix every line with the name of the file it came from.
+    pub show_path: bool,
+    pub line_number: bool,
+    /// Print an empty line after every record, to keep the paragraphs of --paragraph apart.
+    pub paragraph: bool,
+}
+
+impl Printer {
+    pub fn new(config: &Config, show_path: bool) -> Printer {
+        Printer {
+            color: config.use_color(),
+            show_path,
+            line_number: config.line_number,
+            paragraph: config.records == RecordMode::Paragraph,
+        }
+    }
+
+    /// Prints one line. Matching lines are marked with ':' and context lines with '-',
+    /// like "poem.txt:6:How dreary" or "poem.txt-7-How public".
+    ///
+    /// With -U and --paragraph, the "line" is a record that can be several lines long.
+    /// Then every one of its lines is printed with its own prefix.
+    pub fn line(
+        &self,
+        out: &mut impl Write,
+        name: &str,
+        line: Line,
+        spans: &[Range<usize>],
+        separator: char,
+    ) -> io::Result<()> {
+        let separator = self.paint(&separator.to_string(), CYAN);
+        let mut start = 0;
+
+        // A -U record can end with the line ending of its match, but we already end every line.
+        let text = line.text.strip_suffix('\n').unwrap_or(line.text);
+        for (i, raw) in text.split('\n').enumerate() {
+            let text = raw.strip_suffix('\r').unwrap_or(raw);
+
+            // The spans are cut at the line breaks, and made relative to this line.
+            let end = start + text.len();
+            let line_spans: Vec<Range<usize>> = spans
+                .iter()
+                .filter(|span| span.start < end && span.end > start)
+                .map(|span| span.start.max(start) - start..span.end.min(end) - start)
+                .collect();
+
+            if self.show_path {
+                write!(out, "{}{}", self.paint(name, MAGENTA), separator)?;
+            }
+            if self.line_number {
+                write!(out, "{}{}", self.paint(&(line.number + i).to_string(), GREEN), separator)?;
+            }
+            writeln!(out, "{}", self.highlight(text, &line_spans))?;
+
+            start += raw.len() + 1;
+        }
+
+        if self.paragraph {
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+
+    /// Prints the "--" between two groups of lines.
+    pub fn separator(&self, out: &mut impl Write) -> io::Result<()> {
+        writeln!(out, "{}", self.paint("--", CYAN))
+    }
+
+    /// Prints the name of a file, for -l.
+    pub fn path(&self, out: &mut impl Write, name: &str) -> io::Result<()> {
+        writeln!(out, "{}", self.paint(name, MAGENTA))
+    }
+
+    /// Prints the number of selected lines, for -c.
+    pub fn count(&self, out: &mut impl Write, name: &str, count: usize) -> io::Result<()> {
+        if self.show_path {
+            write!(out, "{}{}", self.paint(name, MAGENTA), self.paint(":", CYAN))?;
+        }
+        writeln!(out, "{}", count)
+    }
+
+    /// Returns the line with every span in bold red.
+    pub fn highlight(&self, text: &str, spans: &[Range<usize>]) -> String {
+        if !self.color {
+            return text.to_string();
+        }
+
+        let mut highlighted = String::with_capacity(text.len());
+        let mut last = 0;
+
+        for span in spans.iter().filter(|span| !span.is_empty()) {
+            highlighted.push_str(&text[last..span.start]);
+            highlighted.push_str(&self.paint(&text[span.clone()], BOLD_RED));
+            last = span.end;
+        }
+
+        highlighted.push_str(&text[last..]);
+        highlighted
+    }
+
+    fn paint(&self, text: &str, style: &str) -> String {
+        match self.color {
+            true => format!("\x1b[{}m{}\x1b[0m", style, text),
+            false => text.to_string(),
+        }
+    }
+}
+
+// The ANSI codes of the styles we use.
+const BOLD_RED: &str = "1;31";
+const GREEN: &str = "32";
+const MAGENTA: &str = "35";
+const CYAN: &str = "36";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COLOR: Printer = Printer { color: true, show_path: true, line_number: true, paragraph: false };
+    const PLAIN: Printer = Printer { color: false, show_path: true, line_number: true, paragraph: false };
+
+    #[test]
+    fn highlights_every_span() {
+        assert_eq!(
+            "\x1b[1;31mto\x1b[0m be or not \x1b[1;31mto\x1b[0m be",
+            COLOR.highlight("to be or not to be", &[0..2, 13..15])
+        );
+        assert_eq!("Rust:", COLOR.highlight("Rust:", &[Range { start: 2, end: 2 }]));
+    }
+
+    #[test]
+    fn colors_the_prefix() {
+        let mut out = Vec::new();
+        let line = Line { number: 7, offset: 0, text: "How public" };
+        COLOR.line(&mut out, "poem.txt", line, &[Range { start: 4, end: 10 }], ':').unwrap();
+
+        assert_eq!(
+            "\x1b[35mpoem.txt\x1b[0m\x1b[36m:\x1b[0m\x1b[32m7\x1b[0m\x1b[36m:\x1b[0mHow \x1b[1;31mpublic\x1b[0m\n",
+            String::from_utf8(out).unwrap()
+        );
+    }
+
+    #[test]
+    fn without_color_the_output_is_plain() {
+        let mut out = Vec::new();
+        let line = Line { number: 7, offset: 0, text: "How public" };
+        PLAIN.line(&mut out, "poem.txt", line, &[Range { start: 4, end: 10 }], '-').unwrap();
+        PLAIN.separator(&mut out).unwrap();
+        PLAIN.count(&mut out, "poem.txt", 3).unwrap();
+
+        assert_eq!("poem.txt-7-How public\n--\npoem.txt:3\n", String::from_utf8(out).unwrap());
+    }
+
+    #[test]
+    fn records_of_several_lines() {
+        let record = Line { number: 2, offset: 0, text: "ERROR boom\r\n  at main.rs" };
+        let spans = [Range { start: 6, end: 16 }];
+
+        let mut out = Vec::new();
+        Printer { paragraph: true, ..PLAIN }.line(&mut out, "app.log", record, &spans, ':').unwrap();
+        assert_eq!("app.log:2:ERROR boom\napp.log:3:  at main.rs\n\n", String::from_utf8(out).unwrap());
+
+        // The match is cut in two at the line break.
+        let mut out = Vec::new();
+        Printer { show_path: false, line_number: false, ..COLOR }.line(&mut out, "app.log", record, &spans, ':').unwrap();
+        assert_eq!(
+            "ERROR \x1b[1;31mboom\x1b[0m\n\x1b[1;31m  at\x1b[0m main.rs\n",
+            String::from_utf8(out).unwrap()
+        );
+    }
+}
@@ -0,0 +1,209 @@
+// Searching many files at the same time, on a pool of worker threads.
+//
+// This works a lot like the ThreadPool in the web_server crate: the main thread sends
+// jobs (the files to search) over a channel, and every worker takes the next job from
+// the shared receiver. Instead of printing, a worker writes the output for its file into
+// a Vec<u8>, and sends it back to the main thread, together with the index of the file.
+//
+// Workers finish in any order, so the main thread keeps results that arrive too early
+// in a BTreeMap, until all files before them are printed. That way the output of every
+// file stays in one piece, and is in the same order as when searching one file at a time.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::{mpsc, Mutex};
+use std::thread;
+
+use crate::walk::Paths;
+use crate::{context_lines, file_error, search_path, Config, Messages, Printer, Searcher, Stats};
+
+type Job = (usize, PathBuf);
+type Output = (usize, io::Result<(Vec<u8>, Stats)>);
+
+/// Searches all `paths` with `threads` workers, and writes the output to `out` in order.
+/// Returns the stats of all the searches added together.
+pub fn search_paths(
+    config: &Config,
+    searcher: &Searcher,
+    paths: Paths,
+    threads: usize,
+    printer: &Printer,
+    out: &mut impl Write,
+    messages: &Messages,
+) -> Result<Stats, Box<dyn Error>> {
+    let (job_sender, job_receiver) = mpsc::channel::<Job>();
+    let job_receiver = Mutex::new(job_receiver);
+
+    // With a scope, the workers can borrow the config and the searcher,
+    // because all of them are joined before the scope ends.
+    thread::scope(|scope| {
+        // Moved into the scope, so an early return drops it, which lets the workers stop.
+        let job_sender = job_sender;
+        let (output_sender, output_receiver) = mpsc::channel::<Output>();
+
+        for _ in 0..threads {
+            let output_sender = output_sender.clone();
+            let job_receiver = &job_receiver;
+
+            scope.spawn(move || loop {
+                // The lock is released at the end of this statement, before the search starts.
+                let job = job_receiver.lock().unwrap().recv();
+                let (index, path) = match job {
+                    Ok(job) => job,
+                    Err(_) => break,
+                };
+
+                // Errors while reading are reported by search_path() itself,
+                // and writing to a Vec can't fail, so this is hardly ever an error.
+                let mut buffer = Vec::new();
+                let result = search_path(config, searcher, &path, printer, &mut buffer, &mut false, messages)
+                    .map(|stats| (buffer, stats));
+
+                // The main thread stopped listening (because of an error), so we can stop too.
+                if output_sender.send((index, result)).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(output_sender);
+
+        let mut ordered = OrderedPrinter::new(config, printer);
+
+        for (index, path) in paths.enumerate() {
+            match path {
+                Ok(path) => job_sender.send((index, path))?,
+                Err(err) => {
+                    // We still have to add something for this index, or the files after it never get printed.
+                    let stats = file_error(messages, &err);
+                    ordered.add(index, Ok((Vec::new(), stats)), out)?;
+                }
+            }
+
+            // Print whatever is ready, so the output does not pile up in memory.
+            while let Ok((index, result)) = output_receiver.try_recv() {
+                ordered.add(index, result, out)?;
+            }
+        }
+
+        // No more jobs. The workers stop when the channel is empty,
+        // and the loop below ends when all of them have stopped.
+        drop(job_sender);
+
+        for (index, result) in output_receiver {
+            ordered.add(index, result, out)?;
+        }
+
+        Ok(ordered.stats)
+    })
+}
+
+// Prints the output of every file in order of the index of the file.
+struct OrderedPrinter<'a> {
+    printer: &'a Printer,
+    next: usize,
+    pending: BTreeMap<usize, io::Result<(Vec<u8>, Stats)>>,
+    stats: Stats,
+    has_context: bool,
+    printed_group: bool,
+}
+
+impl OrderedPrinter<'_> {
+    fn new<'a>(config: &Config, printer: &'a Printer) -> OrderedPrinter<'a> {
+        let (before, after) = context_lines(config);
+
+        OrderedPrinter {
+            printer,
+            next: 0,
+            pending: BTreeMap::new(),
+            stats: Stats::default(),
+            // JSON lines have no separators, every object says which file it belongs to.
+            has_context: (before > 0 || after > 0) && !config.json,
+            printed_group: false,
+        }
+    }
+
+    fn add(&mut self, index: usize, result: io::Result<(Vec<u8>, Stats)>, out: &mut impl Write) -> io::Result<()> {
+        self.pending.insert(index, result);
+
+        while let Some(result) = self.pending.remove(&self.next) {
+            let (output, stats) = result?;
+            self.stats += stats;
+
+            // The workers don't know about each other, so the "--" between the
+            // groups of two files is added here.
+            if !output.is_empty() {
+                if self.has_context && self.printed_group {
+                    self.printer.separator(out)?;
+                }
+                self.printed_group = true;
+                out.write_all(&output)?;
+            }
+
+            self.next += 1;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::config::parse;
+    use crate::{no_messages, run_to};
+
+    fn run(args: &[&str]) -> String {
+        let mut out = Vec::new();
+        run_to(parse(args).unwrap(), &mut out, &no_messages).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn parallel_output_is_the_same_as_sequential() {
+        let dir = tempfile::tempdir().unwrap();
+        for i in 0..50 {
+            let content: String = (0..100).map(|line| format!("file {} line {} to\n", i, line)).collect();
+            fs::write(dir.path().join(format!("{:02}.txt", i)), content).unwrap();
+        }
+        let root = dir.path().to_str().unwrap();
+
+        for args in [vec!["-r", "-n", "line 5"], vec!["-r", "-C1", "line 50 "], vec!["-r", "-c", "9 to"]] {
+            let sequential = run(&[&["-j1"], &args[..], &[root]].concat());
+            let parallel = run(&[&["-j8"], &args[..], &[root]].concat());
+
+            assert!(!sequential.is_empty());
+            assert_eq!(sequential, parallel);
+        }
+    }
+
+    #[test]
+    fn parallel_json_has_no_separators() {
+        let dir = tempfile::tempdir().unwrap();
+        for i in 0..4 {
+            fs::write(dir.path().join(format!("{}.txt", i)), "before
+foo
+after
+").unwrap();
+        }
+        let root = dir.path().to_str().unwrap();
+
+        // The elapsed times differ, so we only compare the kinds of the objects.
+        let kinds = |output: String| -> Vec<String> {
+            output
+                .lines()
+                .map(|line| {
+                    let object: serde_json::Value = serde_json::from_str(line).unwrap();
+                    object["type"].as_str().unwrap().to_string()
+                })
+                .collect()
+        };
+
+        let sequential = kinds(run(&["-j1", "--json", "-C1", "-r", "foo", root]));
+        let parallel = kinds(run(&["-j4", "--json", "-C1", "-r", "foo", root]));
+        assert_eq!(4 * 5 + 1, sequential.len());
+        assert_eq!(sequential, parallel);
+    }
+}
@@ -0,0 +1,203 @@
+// Rewriting files with --replace, for --in-place and --dry-run.
+//
+// A file is never half written: the new content goes into a temporary file in the same
+// directory first, which is then renamed over the original. A rename within one directory
+// is atomic, so other programs see either the old file or the new one, and if something
+// goes wrong in between, the original is left alone.
+//
+// With --dry-run, nothing is written. Instead we print what would change as a unified diff,
+// which can be reviewed, or applied later with `git apply --unidiff-zero`.
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use tempfile::NamedTempFile;
+
+use crate::{walk, Config, Matcher};
+
+/// One line that is changed by the replacement.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Change<'a> {
+    pub number: usize,
+    pub old: &'a str,
+    pub new: String,
+    /// The line ending, which stays the same. Empty if the last line has none.
+    pub ending: &'a str,
+}
+
+/// Replaces the matches in one file, or prints a diff of them with --dry-run.
+/// Returns how many lines were (or would be) changed.
+pub fn rewrite(config: &Config, matcher: &dyn Matcher, path: &Path, out: &mut impl Write) -> io::Result<usize> {
+    if path == Path::new("-") {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "standard input can't be changed in place"));
+    }
+
+    let bytes = fs::read(path)?;
+    if walk::is_binary(&bytes) {
+        return Ok(0);
+    }
+
+    // Unlike when searching, we can't read the file lossily, because that would change
+    // the invalid bytes when we write it back.
+    let content = String::from_utf8(bytes).map_err(|_| {
+        io::Error::new(io::ErrorKind::InvalidData, format!("{}: not valid UTF-8, not changing it", path.display()))
+    })?;
+
+    let replacement = config.replace.as_deref().unwrap_or_default();
+    let changes = changes(matcher, &content, replacement);
+    if changes.is_empty() {
+        return Ok(0);
+    }
+
+    if config.dry_run {
+        diff(out, &path.display().to_string(), &changes)?;
+    } else {
+        write_atomically(path, &apply(&content, &changes), config.backup.as_deref())?;
+    }
+
+    Ok(changes.len())
+}
+
+/// Returns every line of `content` that changes when the matches are replaced.
+pub fn changes<'a>(matcher: &dyn Matcher, content: &'a str, replacement: &str) -> Vec<Change<'a>> {
+    content
+        .split_inclusive('\n')
+        .enumerate()
+        .filter_map(|(index, line)| {
+            let old = line.trim_end_matches(['\n', '\r']);
+            let ending = &line[old.len()..];
+            let (new, _) = matcher.replace_all(old, replacement);
+
+            match new == old {
+                true => None,
+                false => Some(Change { number: index + 1, old, new, ending }),
+            }
+        })
+        .collect()
+}
+
+// Returns `content` with every changed line swapped for its new version.
+fn apply(content: &str, changes: &[Change]) -> String {
+    let mut changes = changes.iter().peekable();
+
+    content
+        .split_inclusive('\n')
+        .enumerate()
+        .map(|(index, line)| match changes.next_if(|change| change.number == index + 1) {
+            Some(change) => format!("{}{}", change.new, change.ending),
+            None => line.to_string(),
+        })
+        .collect()
+}
+
+fn write_atomically(path: &Path, content: &str, backup: Option<&str>) -> io::Result<()> {
+    // The temporary file has to be in the same directory, because a rename
+    // to another file system is not atomic (or not possible at all).
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+
+    let mut temp = NamedTempFile::new_in(dir)?;
+    temp.write_all(content.as_bytes())?;
+    temp.as_file().sync_all()?;
+    // The new file should keep the permissions of the old one, like being executable.
+    temp.as_file().set_permissions(fs::metadata(path)?.permissions())?;
+
+    if let Some(suffix) = backup {
+        let mut backup_path = PathBuf::from(path).into_os_string();
+        backup_path.push(suffix);
+        fs::copy(path, backup_path)?;
+    }
+
+    temp.persist(path).map_err(|err| err.error)?;
+    Ok(())
+}
+
+/// Prints the changes as a unified diff without context lines.
+/// Changed lines that follow each other are put in the same hunk.
+pub fn diff(out: &mut impl Write, name: &str, changes: &[Change]) -> io::Result<()> {
+    writeln!(out, "--- a/{}", name)?;
+    writeln!(out, "+++ b/{}", name)?;
+
+    for hunk in changes.chunk_by(|a, b| a.number + 1 == b.number) {
+        writeln!(out, "@@ -{0},{1} +{0},{1} @@", hunk[0].number, hunk.len())?;
+        for change in hunk {
+            diff_line(out, '-', change.old, change.ending)?;
+        }
+        for change in hunk {
+            diff_line(out, '+', &change.new, change.ending)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn diff_line(out: &mut impl Write, prefix: char, text: &str, ending: &str) -> io::Result<()> {
+    writeln!(out, "{}{}", prefix, text)?;
+    if ending.is_empty() {
+        writeln!(out, "\\ No newline at end of file")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::parse;
+    use crate::RegexMatcher;
+
+    const POEM: &str = "I'm nobody! Who are you?\nAre you nobody, too?\nThen there's a pair of us - don't tell!\nThey'd banish us, you know.";
+
+    #[test]
+    fn dry_run_prints_a_diff() {
+        let matcher = RegexMatcher::new(&["(no)body".to_string()], false).unwrap();
+        let changes = changes(&matcher, POEM, "${1}one");
+        assert_eq!(2, changes.len());
+        assert_eq!("I'm noone! Who are you?", changes[0].new);
+
+        let mut out = Vec::new();
+        diff(&mut out, "poem.txt", &changes).unwrap();
+        assert_eq!(
+            "\
+--- a/poem.txt
++++ b/poem.txt
+@@ -1,2 +1,2 @@
+-I'm nobody! Who are you?
+-Are you nobody, too?
++I'm noone! Who are you?
++Are you noone, too?
+",
+            String::from_utf8(out).unwrap()
+        );
+    }
+
+    #[test]
+    fn in_place_keeps_line_endings_and_makes_a_backup() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("poem.txt");
+        fs::write(&path, "Are you nobody, too?\r\nHow dreary to be somebody!").unwrap();
+
+        let config = parse(&["--replace", "2", "--in-place", "--backup", ".orig", "to", "unused"]).unwrap();
+        let changed = rewrite(&config, config.searcher().unwrap().matcher(), &path, &mut io::sink()).unwrap();
+
+        assert_eq!(2, changed);
+        assert_eq!("Are you nobody, 2o?\r\nHow dreary 2 be somebody!", fs::read_to_string(&path).unwrap());
+        assert_eq!(
+            "Are you nobody, too?\r\nHow dreary to be somebody!",
+            fs::read_to_string(dir.path().join("poem.txt.orig")).unwrap()
+        );
+    }
+
+    #[test]
+    fn no_newline_at_end_of_file() {
+        let matcher = RegexMatcher::new(&["know".to_string()], false).unwrap();
+        let mut out = Vec::new();
+        diff(&mut out, "poem.txt", &changes(&matcher, POEM, "see")).unwrap();
+
+        assert!(String::from_utf8(out)
+            .unwrap()
+            .ends_with("@@ -4,1 +4,1 @@\n-They'd banish us, you know.\n\\ No newline at end of file\n+They'd banish us, you see.\n\\ No newline at end of file\n"));
+    }
+}
@@ -0,0 +1,639 @@
+// The search itself: which lines match, where in the line they match,
+// and which lines around them should be printed as context (-A, -B and -C).
+//
+// Instead of returning only &str slices, every line knows its line number and
+// the byte offset where it starts in the content, so the output can say exactly
+// where something was found.
+//
+// Content that is not valid UTF-8 is converted lossily before it is searched, and every
+// invalid sequence becomes one '�', which is 3 bytes long. So there are two kinds of
+// offsets: Line::offset is always an offset in the original bytes (of the file), but the
+// spans of a match are byte ranges in Line::text, which is the converted text.
+
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::io::{self, BufRead};
+use std::ops::Range;
+
+use crate::{Matcher, RecordMode};
+
+/// One line of the searched content, without its line ending.
+/// Only a -U record whose match ends with a line ending keeps that line ending, as part of the match.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Line<'a> {
+    /// The line number, starting at 1.
+    pub number: usize,
+    /// The byte offset of the start of the line in the original content, before it was converted.
+    pub offset: usize,
+    /// The line, converted lossily if it was not valid UTF-8.
+    pub text: &'a str,
+}
+
+/// A line that was selected by the search.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Match<'a> {
+    pub line: Line<'a>,
+    /// The byte ranges inside of `line.text` (not the original bytes) that matched.
+    /// Empty for inverted (-v) matches.
+    pub spans: Vec<Range<usize>>,
+}
+
+/// Something to print when showing matches with context.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Entry<'a> {
+    Match(Match<'a>),
+    Context(Line<'a>),
+    /// The "--" between two groups of lines that are not next to each other.
+    Separator,
+}
+
+/// Splits the content into lines, just like str::lines() ("\n" or "\r\n" endings),
+/// but also keeps track of line numbers and offsets.
+pub fn lines(content: &str) -> impl Iterator<Item = Line<'_>> {
+    content
+        .split_inclusive('\n')
+        .scan(0, |offset, raw| {
+            let start = *offset;
+            *offset += raw.len();
+            Some((start, raw))
+        })
+        .enumerate()
+        .map(|(index, (offset, raw))| {
+            let text = raw.strip_suffix('\n').unwrap_or(raw);
+            let text = text.strip_suffix('\r').unwrap_or(text);
+            Line { number: index + 1, offset, text }
+        })
+}
+
+/// Splits the content into paragraphs: blocks of lines between blank lines. A paragraph is
+/// returned as one Line, with the number and offset of its first line, and the text of all
+/// of its lines (without the line ending of the last one). The blank lines are left out.
+pub fn paragraphs(content: &str) -> impl Iterator<Item = Line<'_>> {
+    let mut lines = lines(content).peekable();
+    let is_blank = |line: &Line| line.text.trim().is_empty();
+
+    std::iter::from_fn(move || {
+        let first = loop {
+            let line = lines.next()?;
+            if !is_blank(&line) {
+                break line;
+            }
+        };
+
+        let mut last = first;
+        while let Some(line) = lines.next_if(|line| !is_blank(line)) {
+            last = line;
+        }
+
+        let text = &content[first.offset..last.offset + last.text.len()];
+        Some(Line { number: first.number, offset: first.offset, text })
+    })
+}
+
+/// Returns the byte ranges of every match in the line, from left to right.
+pub fn find_spans<M: Matcher + ?Sized>(matcher: &M, text: &str) -> Vec<Range<usize>> {
+    let mut spans = Vec::new();
+    let mut start = 0;
+
+    while start <= text.len() {
+        let span = match matcher.find_at(text, start) {
+            Some(span) => span,
+            None => break,
+        };
+
+        // An empty match would find itself again and again, so we step over the next character.
+        start = if span.is_empty() {
+            span.end + text[span.end..].chars().next().map_or(1, char::len_utf8)
+        } else {
+            span.end
+        };
+        spans.push(span);
+    }
+
+    spans
+}
+
+/// Returns every line that matches, or with `invert` every line that does not match.
+pub fn search_lines<'a>(matcher: &dyn Matcher, content: &'a str, invert: bool) -> Vec<Match<'a>> {
+    lines(content)
+        .filter_map(|line| {
+            let spans = find_spans(matcher, line.text);
+            match (spans.is_empty(), invert) {
+                (false, false) => Some(Match { line, spans }),
+                (true, true) => Some(Match { line, spans: Vec::new() }),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// Puts `before` lines in front of, and `after` lines behind every match.
+///
+/// Windows that overlap or touch are merged into one group, and groups that are
+/// not next to each other are separated by an `Entry::Separator`.
+pub fn with_context<'a>(content: &'a str, matches: Vec<Match<'a>>, before: usize, after: usize) -> Vec<Entry<'a>> {
+    let mut window = ContextWindow::new(before, after);
+    let mut matches = matches.into_iter().peekable();
+    let mut entries = Vec::new();
+
+    for line in lines(content) {
+        let entry = match matches.next_if(|m| m.line.number == line.number) {
+            Some(m) => Entry::Match(m),
+            None => Entry::Context(line),
+        };
+        let is_match = matches!(entry, Entry::Match(_));
+        let pushed: Result<(), Infallible> = window.push(line.number, entry, is_match, &mut |emitted| {
+            entries.push(match emitted {
+                Emit::Line(entry) => entry,
+                Emit::Separator => Entry::Separator,
+            });
+            Ok(())
+        });
+        // Pushing into a Vec can not fail.
+        let Ok(()) = pushed;
+    }
+
+    entries
+}
+
+/// Searches `reader` line by line, and calls `emit` for every entry that should be printed.
+/// If `emit` returns an error (e.g. because stdout was closed), the search stops with that error.
+/// Errors while reading are converted into the same error type, so the caller can tell them apart
+/// from its own errors if it wants to.
+///
+/// Only the current line (and the last `before` lines, for context) are kept in memory,
+/// so this works for files that are much larger than memory, and for stdin.
+/// Lines that are not valid UTF-8 are converted lossily, so invalid bytes become '�'.
+/// The offsets of the lines still count the original bytes.
+///
+/// Returns how many lines were selected.
+pub fn search_reader<R: BufRead, E: From<io::Error>>(
+    mut reader: R,
+    matcher: &dyn Matcher,
+    invert: bool,
+    before: usize,
+    after: usize,
+    mut emit: impl FnMut(Entry<'_>) -> Result<(), E>,
+) -> Result<usize, E> {
+    let mut window = ContextWindow::new(before, after);
+    let mut buffer = Vec::new();
+    let mut number = 0;
+    let mut offset = 0;
+    let mut selected = 0;
+
+    loop {
+        buffer.clear();
+        let read = reader.read_until(b'\n', &mut buffer)?;
+        if read == 0 {
+            break;
+        }
+
+        number += 1;
+        let raw = buffer.strip_suffix(b"\n").unwrap_or(&buffer);
+        let raw = raw.strip_suffix(b"\r").unwrap_or(raw);
+        let text = String::from_utf8_lossy(raw).into_owned();
+
+        let spans = find_spans(matcher, &text);
+        let is_match = spans.is_empty() == invert;
+        if is_match {
+            selected += 1;
+        }
+
+        let spans = if invert { Vec::new() } else { spans };
+        let owned = OwnedLine { number, offset, text, spans, is_match };
+        window.push(number, owned, is_match, &mut |emitted| match emitted {
+            Emit::Line(line) if line.is_match => {
+                emit(Entry::Match(Match { line: line.as_line(), spans: line.spans.clone() }))
+            }
+            Emit::Line(line) => emit(Entry::Context(line.as_line())),
+            Emit::Separator => emit(Entry::Separator),
+        })?;
+
+        offset += read;
+    }
+
+    Ok(selected)
+}
+
+/// Searches the whole content at once, for -U and --paragraph, and calls `emit` for every entry,
+/// just like search_reader() does. Every entry is a record, which can be several lines long.
+///
+/// With RecordMode::Multiline, the lines that a match touches become one record, and every other
+/// line is a record of its own. With RecordMode::Paragraph, every paragraph is a record.
+/// Context counts records as well, so with --paragraph, -C1 prints the paragraphs around a match.
+///
+/// Returns how many records were selected.
+pub fn search_records<E>(
+    content: &str,
+    matcher: &dyn Matcher,
+    mode: RecordMode,
+    invert: bool,
+    before: usize,
+    after: usize,
+    mut emit: impl FnMut(Entry<'_>) -> Result<(), E>,
+) -> Result<usize, E> {
+    let records = match mode {
+        RecordMode::Line => lines(content).map(|line| (line, find_spans(matcher, line.text))).collect(),
+        RecordMode::Multiline => multiline_records(matcher, content),
+        RecordMode::Paragraph => paragraphs(content).map(|record| (record, find_spans(matcher, record.text))).collect(),
+    };
+
+    let mut window = ContextWindow::new(before, after);
+    let mut selected = 0;
+
+    for (index, (record, spans)) in records.into_iter().enumerate() {
+        let is_match = spans.is_empty() == invert;
+        let entry = match (is_match, invert) {
+            (true, false) => Entry::Match(Match { line: record, spans }),
+            (true, true) => Entry::Match(Match { line: record, spans: Vec::new() }),
+            (false, _) => Entry::Context(record),
+        };
+        selected += usize::from(is_match);
+
+        window.push(index + 1, entry, is_match, &mut |emitted| match emitted {
+            Emit::Line(entry) => emit(entry),
+            Emit::Separator => emit(Entry::Separator),
+        })?;
+    }
+
+    Ok(selected)
+}
+
+// Matches the whole content at once, and groups the lines into records: the lines from where
+// a match starts to where it ends are one record (together with the lines of other matches that
+// share one of those lines), and every line without a match is a record of its own.
+fn multiline_records<'a>(matcher: &dyn Matcher, content: &'a str) -> Vec<(Line<'a>, Vec<Range<usize>>)> {
+    let mut spans = find_spans(matcher, content).into_iter().peekable();
+    let mut lines = lines(content).peekable();
+    let mut records = Vec::new();
+
+    while let Some(first) = lines.next() {
+        let mut last = first;
+        let mut record_spans = Vec::new();
+
+        // The record grows while the next match starts in it, up to the line where the match ends.
+        loop {
+            let end = lines.peek().map_or(content.len(), |next| next.offset);
+            let span = match spans.next_if(|span| span.start < end) {
+                Some(span) => span,
+                None => break,
+            };
+
+            let last_byte = match span.is_empty() {
+                true => span.start,
+                false => span.end - 1,
+            };
+            while let Some(line) = lines.next_if(|line| line.offset <= last_byte) {
+                last = line;
+            }
+            record_spans.push(span.start - first.offset..span.end - first.offset);
+        }
+
+        // A match can end with the line ending of the last line. Then the record keeps it,
+        // so the match is still whole, and e.g. --replace finds it again in the record.
+        let mut end = last.offset + last.text.len();
+        if record_spans.iter().any(|span| first.offset + span.end > end) {
+            end += line_ending(&content[end..]).len();
+        }
+        let text = &content[first.offset..end];
+        records.push((Line { number: first.number, offset: first.offset, text }, record_spans));
+    }
+
+    records
+}
+
+fn line_ending(rest: &str) -> &str {
+    match rest.starts_with("\r\n") {
+        true => "\r\n",
+        false => &rest[..usize::from(rest.starts_with('\n'))],
+    }
+}
+
+/// Turns offsets in lossily converted content back into offsets in the original bytes.
+/// search_records() only sees the converted content, so its offsets need this.
+pub struct OriginalOffsets {
+    // For every invalid sequence: the offsets right after it, in the converted content
+    // and in the original bytes. Between two of them, both grow at the same rate.
+    points: Vec<(usize, usize)>,
+}
+
+impl OriginalOffsets {
+    pub fn new(bytes: &[u8]) -> OriginalOffsets {
+        let mut points = Vec::new();
+        let (mut converted, mut original) = (0, 0);
+
+        // This is how String::from_utf8_lossy() works as well: one '�' for every invalid chunk.
+        for chunk in bytes.utf8_chunks() {
+            converted += chunk.valid().len();
+            original += chunk.valid().len();
+            if !chunk.invalid().is_empty() {
+                converted += char::REPLACEMENT_CHARACTER.len_utf8();
+                original += chunk.invalid().len();
+                points.push((converted, original));
+            }
+        }
+
+        OriginalOffsets { points }
+    }
+
+    /// The offset in the original bytes of `converted`, which must not point inside of a '�'.
+    pub fn original(&self, converted: usize) -> usize {
+        match self.points.partition_point(|&(point, _)| point <= converted) {
+            0 => converted,
+            index => {
+                let (point, original) = self.points[index - 1];
+                original + (converted - point)
+            }
+        }
+    }
+}
+
+// A line read from a reader, that we have to own, because the buffer is reused for the next line.
+struct OwnedLine {
+    number: usize,
+    offset: usize,
+    text: String,
+    spans: Vec<Range<usize>>,
+    is_match: bool,
+}
+
+impl OwnedLine {
+    fn as_line(&self) -> Line<'_> {
+        Line { number: self.number, offset: self.offset, text: &self.text }
+    }
+}
+
+enum Emit<T> {
+    Line(T),
+    Separator,
+}
+
+// Decides which lines to print around the matches, while seeing the lines one at a time.
+//
+// The last `before` lines are kept in a buffer, in case the next line is a match.
+// After a match, the next `after` lines are printed right away.
+struct ContextWindow<T> {
+    before: usize,
+    after: usize,
+    buffer: VecDeque<(usize, T)>,
+    after_left: usize,
+    // The line number of the last line we printed, so we know when to print a separator.
+    last_printed: usize,
+}
+
+impl<T> ContextWindow<T> {
+    fn new(before: usize, after: usize) -> ContextWindow<T> {
+        ContextWindow { before, after, buffer: VecDeque::new(), after_left: 0, last_printed: 0 }
+    }
+
+    fn push<E>(
+        &mut self,
+        number: usize,
+        line: T,
+        is_match: bool,
+        emit: &mut impl FnMut(Emit<T>) -> Result<(), E>,
+    ) -> Result<(), E> {
+        if is_match {
+            // Without context, there are no groups to separate.
+            let first = self.buffer.front().map_or(number, |(n, _)| *n);
+            let has_context = self.before > 0 || self.after > 0;
+            if has_context && self.last_printed > 0 && first > self.last_printed + 1 {
+                emit(Emit::Separator)?;
+            }
+
+            for (_, line) in self.buffer.drain(..) {
+                emit(Emit::Line(line))?;
+            }
+            emit(Emit::Line(line))?;
+            self.after_left = self.after;
+            self.last_printed = number;
+        } else if self.after_left > 0 {
+            emit(Emit::Line(line))?;
+            self.after_left -= 1;
+            self.last_printed = number;
+        } else if self.before > 0 {
+            if self.buffer.len() == self.before {
+                self.buffer.pop_front();
+            }
+            self.buffer.push_back((number, line));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LiteralMatcher;
+
+    fn literal(query: &str) -> LiteralMatcher {
+        LiteralMatcher::new(&[query.to_string()])
+    }
+
+    // Turns the entries into grep-like output, so the tests are easy to read.
+    fn render(entries: &[Entry]) -> Vec<String> {
+        entries
+            .iter()
+            .map(|entry| match entry {
+                Entry::Match(m) => format!("{}:{}", m.line.number, m.line.text),
+                Entry::Context(line) => format!("{}-{}", line.number, line.text),
+                Entry::Separator => "--".to_string(),
+            })
+            .collect()
+    }
+
+    const CONTENT: &str = "one\ntwo\r\nthree\nfour\nfive\nsix\nseven\neight\nnine\nten";
+
+    #[test]
+    fn lines_know_their_number_and_offset() {
+        let lines: Vec<Line> = lines("ab\r\nc\n\nd").collect();
+        assert_eq!(
+            vec![
+                Line { number: 1, offset: 0, text: "ab" },
+                Line { number: 2, offset: 4, text: "c" },
+                Line { number: 3, offset: 6, text: "" },
+                Line { number: 4, offset: 7, text: "d" },
+            ],
+            lines
+        );
+    }
+
+    #[test]
+    fn finds_every_span_in_a_line() {
+        assert_eq!(vec![0..2, 3..5, 11..13], find_spans(&literal("to"), "to to, not to"));
+        assert_eq!(Vec::<Range<usize>>::new(), find_spans(&literal("xyz"), "to to"));
+        // An empty pattern matches between every character, without looping forever.
+        assert_eq!(vec![0..0, 1..1, 3..3], find_spans(&literal(""), "aü"));
+    }
+
+    #[test]
+    fn matches_have_line_numbers_offsets_and_spans() {
+        let matches = search_lines(&literal("e"), "abc\nxyz\nhere", false);
+        assert_eq!(1, matches.len());
+        assert_eq!(Line { number: 3, offset: 8, text: "here" }, matches[0].line);
+        assert_eq!(vec![1..2, 3..4], matches[0].spans);
+
+        let inverted = search_lines(&literal("e"), "abc\nxyz\nhere", true);
+        let numbers: Vec<usize> = inverted.iter().map(|m| m.line.number).collect();
+        assert_eq!(vec![1, 2], numbers);
+    }
+
+    #[test]
+    fn context_before_and_after() {
+        let matches = search_lines(&literal("five"), CONTENT, false);
+        assert_eq!(
+            vec!["3-three", "4-four", "5:five", "6-six"],
+            render(&with_context(CONTENT, matches, 2, 1))
+        );
+    }
+
+    #[test]
+    fn separated_groups() {
+        let matches = search_lines(&literal("t"), CONTENT, false);
+        // "two" (2), "three" (3), "eight" (8), "ten" (10)
+        assert_eq!(
+            vec!["2:two", "3:three", "4-four", "--", "8:eight", "9-nine", "10:ten"],
+            render(&with_context(CONTENT, matches, 0, 1))
+        );
+    }
+
+    #[test]
+    fn overlapping_windows_are_merged() {
+        let matches = search_lines(&literal("f"), CONTENT, false);
+        // "four" (4) and "five" (5) are next to each other.
+        assert_eq!(
+            vec!["2-two", "3-three", "4:four", "5:five", "6-six", "7-seven"],
+            render(&with_context(CONTENT, matches, 2, 2))
+        );
+    }
+
+    #[test]
+    fn streams_from_a_reader() {
+        let mut entries = Vec::new();
+        let selected = search_reader(CONTENT.as_bytes(), &literal("t"), false, 0, 1, |entry| {
+            entries.push(render(&[entry]).remove(0));
+            Ok::<(), io::Error>(())
+        })
+        .unwrap();
+
+        assert_eq!(4, selected);
+        assert_eq!(vec!["2:two", "3:three", "4-four", "--", "8:eight", "9-nine", "10:ten"], entries);
+    }
+
+    #[test]
+    fn stream_offsets_and_inverted_matches() {
+        let mut matches = Vec::new();
+        search_reader("abc\r\nxyz\nhere".as_bytes(), &literal("e"), true, 0, 1, |entry| {
+            matches.push(match entry {
+                Entry::Match(m) => format!("{}:{}:{}", m.line.number, m.line.offset, m.line.text),
+                Entry::Context(line) => format!("{}-{}-{}", line.number, line.offset, line.text),
+                Entry::Separator => "--".to_string(),
+            });
+            Ok::<(), io::Error>(())
+        })
+        .unwrap();
+
+        assert_eq!(vec!["1:0:abc", "2:5:xyz", "3-9-here"], matches);
+    }
+
+    // An invalid byte becomes a 3 byte '�'. The spans are in the converted text, but the offsets
+    // of the lines stay offsets in the original bytes.
+    #[test]
+    fn offsets_count_the_original_bytes() {
+        let mut matches = Vec::new();
+        search_reader(&b"\xffab\n\xfe\xfdab"[..], &literal("ab"), false, 0, 0, |entry| {
+            if let Entry::Match(m) = entry {
+                matches.push((m.line.offset, m.line.text.to_string(), m.spans[0].clone()));
+            }
+            Ok::<(), io::Error>(())
+        })
+        .unwrap();
+
+        assert_eq!(vec![(0, "\u{fffd}ab".to_string(), 3..5), (4, "\u{fffd}\u{fffd}ab".to_string(), 6..8)], matches);
+
+        let offsets = OriginalOffsets::new(b"\xffab\n\xfe\xfdab");
+        assert_eq!(0, offsets.original(0));
+        assert_eq!(4, offsets.original(6));
+        assert_eq!(6, offsets.original(12));
+        assert_eq!(8, offsets.original(14));
+        assert_eq!(3, OriginalOffsets::new(b"ab\n").original(3));
+    }
+
+    #[test]
+    fn stream_handles_invalid_utf8() {
+        let mut lines = Vec::new();
+        let content: &[u8] = b"caf\xe9 latte\nplain tea\n";
+        search_reader(content, &literal("latte"), false, 0, 0, |entry| {
+            if let Entry::Match(m) = entry {
+                lines.push(m.line.text.to_string());
+            }
+            Ok::<(), io::Error>(())
+        })
+        .unwrap();
+
+        assert_eq!(vec!["caf\u{FFFD} latte"], lines);
+    }
+
+    #[test]
+    fn multiline_matches_span_lines() {
+        let log = "INFO start\nERROR boom\n  at main.rs:3\nINFO done\nERROR again\n";
+        let matcher = crate::RegexMatcher::multi_line(&[r"^ERROR.*\n\s+at".to_string()], false).unwrap();
+
+        let mut entries = Vec::new();
+        let selected = search_records(log, &matcher, RecordMode::Multiline, false, 1, 0, |entry| {
+            if let Entry::Match(m) = &entry {
+                assert_eq!(vec![Range { start: 0, end: 15 }], m.spans);
+            }
+            entries.extend(render(&[entry]));
+            Ok::<(), io::Error>(())
+        })
+        .unwrap();
+
+        assert_eq!(1, selected);
+        assert_eq!(vec!["1-INFO start", "2:ERROR boom\n  at main.rs:3"], entries);
+    }
+
+    #[test]
+    fn multiline_records_keep_a_matched_line_ending() {
+        let log = "INFO start\r\nERROR boom\r\nINFO done\n";
+        let matcher = crate::RegexMatcher::multi_line(&[r"boom\r\n".to_string()], false).unwrap();
+
+        let mut matches = Vec::new();
+        search_records(log, &matcher, RecordMode::Multiline, false, 0, 0, |entry| {
+            if let Entry::Match(m) = entry {
+                matches.push((m.line.text.to_string(), m.spans[0].clone()));
+            }
+            Ok::<(), io::Error>(())
+        })
+        .unwrap();
+
+        assert_eq!(vec![("ERROR boom\r\n".to_string(), 6..12)], matches);
+        assert_eq!("ERROR X", matcher.replace_all(&matches[0].0, "X").0);
+    }
+
+    #[test]
+    fn paragraphs_are_records() {
+        let poem = "I'm nobody! Who are you?\nAre you nobody, too?\n\n\nHow dreary to be somebody!\nHow public, like a frog\n";
+        let paragraphs: Vec<Line> = paragraphs(poem).collect();
+        assert_eq!(Line { number: 5, offset: 48, text: "How dreary to be somebody!\nHow public, like a frog" }, paragraphs[1]);
+
+        let mut entries = Vec::new();
+        search_records(poem, &literal("frog"), RecordMode::Paragraph, true, 0, 0, |entry| {
+            entries.extend(render(&[entry]));
+            Ok::<(), io::Error>(())
+        })
+        .unwrap();
+
+        assert_eq!(vec!["1:I'm nobody! Who are you?\nAre you nobody, too?"], entries);
+    }
+
+    #[test]
+    fn context_at_the_edges() {
+        let matches = search_lines(&literal("o"), "one\nx\ny\nz\ntwo", false);
+        assert_eq!(
+            vec!["1:one", "2-x", "3-y", "4-z", "5:two"],
+            render(&with_context("one\nx\ny\nz\ntwo", matches, 5, 5))
+        );
+    }
+}
@@ -0,0 +1,456 @@
+// The library API, for programs that want to search without going through the command line.
+//
+// A Searcher is set up once with a builder, and can then search as many sources as needed:
+// a file, anything that implements BufRead, or a string. It does not print anything itself.
+// Instead, it hands every result to a Sink, and the Sink decides what to do with it.
+// The minigrep binary is just one user of this API, with a Sink that prints to stdout.
+//
+//      let searcher = Searcher::builder().pattern("nobody").ignore_case(true).build()?;
+//      let mut lines = Vec::new();
+//      searcher.search_str(POEM, &mut |m: &Match| {
+//          lines.push(m.line.number);
+//          Ok(())
+//      })?;
+
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+
+use crate::fuzzy::{self, FuzzyMatcher};
+use crate::matcher::{CaseInsensitiveMatcher, LiteralMatcher, Matcher, RegexMatcher};
+use crate::search::{self, Entry};
+use crate::{decompress, walk, Line, Match, PatternSyntax, RecordMode};
+
+/// Receives the results of a search.
+///
+/// Only `matched` has to be implemented. The other methods are called for context lines,
+/// for the "--" between groups of context, and at the start and the end of every source.
+pub trait Sink {
+    /// Errors while reading the source are converted into this error, so a Sink that writes
+    /// somewhere can keep its own errors apart from errors while reading.
+    type Error: From<io::Error>;
+
+    fn matched(&mut self, m: &Match) -> Result<(), Self::Error>;
+
+    fn context(&mut self, _line: &Line) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn separator(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Called before the first line of a source. Binary sources are skipped, without calling this.
+    fn begin(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Called after the last line of a source, with the number of selected lines.
+    fn finish(&mut self, _selected: usize) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// Any closure that takes a Match is a Sink, for when only the matching lines are needed.
+impl<F: FnMut(&Match) -> io::Result<()>> Sink for F {
+    type Error = io::Error;
+
+    fn matched(&mut self, m: &Match) -> io::Result<()> {
+        self(m)
+    }
+}
+
+/// Everything that can go wrong while building a Searcher.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BuildError {
+    MissingPattern,
+    Regex(regex::Error),
+    /// --fuzzy only supports patterns up to fuzzy::MAX_PATTERN_LEN characters.
+    PatternTooLong(String),
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BuildError::MissingPattern => write!(f, "no pattern to search for"),
+            BuildError::Regex(err) => write!(f, "{}", err),
+            BuildError::PatternTooLong(pattern) => write!(
+                f,
+                "pattern '{}' is too long for --fuzzy (at most {} characters)",
+                pattern,
+                fuzzy::MAX_PATTERN_LEN
+            ),
+        }
+    }
+}
+
+impl Error for BuildError {}
+
+/// Sets up a Searcher. Every option has the same default as on the command line.
+#[derive(Debug, Clone)]
+pub struct SearcherBuilder {
+    patterns: Vec<String>,
+    syntax: PatternSyntax,
+    case_sensitive: bool,
+    invert_match: bool,
+    word_regexp: bool,
+    line_regexp: bool,
+    fuzzy: Option<usize>,
+    before_context: usize,
+    after_context: usize,
+    search_zip: bool,
+    records: RecordMode,
+}
+
+impl SearcherBuilder {
+    pub fn new() -> SearcherBuilder {
+        SearcherBuilder {
+            patterns: Vec::new(),
+            syntax: PatternSyntax::Fixed,
+            case_sensitive: true,
+            invert_match: false,
+            word_regexp: false,
+            line_regexp: false,
+            fuzzy: None,
+            before_context: 0,
+            after_context: 0,
+            search_zip: false,
+            records: RecordMode::Line,
+        }
+    }
+
+    /// Adds a pattern. A line matches if it matches any of the patterns.
+    pub fn pattern(mut self, pattern: impl Into<String>) -> SearcherBuilder {
+        self.patterns.push(pattern.into());
+        self
+    }
+
+    pub fn patterns(mut self, patterns: impl IntoIterator<Item = impl Into<String>>) -> SearcherBuilder {
+        self.patterns.extend(patterns.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn syntax(mut self, syntax: PatternSyntax) -> SearcherBuilder {
+        self.syntax = syntax;
+        self
+    }
+
+    pub fn ignore_case(mut self, yes: bool) -> SearcherBuilder {
+        self.case_sensitive = !yes;
+        self
+    }
+
+    pub fn invert_match(mut self, yes: bool) -> SearcherBuilder {
+        self.invert_match = yes;
+        self
+    }
+
+    pub fn word_regexp(mut self, yes: bool) -> SearcherBuilder {
+        self.word_regexp = yes;
+        self
+    }
+
+    pub fn line_regexp(mut self, yes: bool) -> SearcherBuilder {
+        self.line_regexp = yes;
+        self
+    }
+
+    /// Matches the patterns as plain text, with at most `max_distance` typos.
+    pub fn fuzzy(mut self, max_distance: Option<usize>) -> SearcherBuilder {
+        self.fuzzy = max_distance;
+        self
+    }
+
+    /// How many lines of context to give to the Sink before and after every match.
+    pub fn context(mut self, before: usize, after: usize) -> SearcherBuilder {
+        self.before_context = before;
+        self.after_context = after;
+        self
+    }
+
+    /// Decompress .gz, .bz2 and .zst sources while searching them.
+    pub fn search_zip(mut self, yes: bool) -> SearcherBuilder {
+        self.search_zip = yes;
+        self
+    }
+
+    /// Search every line on its own (the default), the whole source at once, or every paragraph.
+    pub fn records(mut self, records: RecordMode) -> SearcherBuilder {
+        self.records = records;
+        self
+    }
+
+    pub fn build(self) -> Result<Searcher, BuildError> {
+        let matcher = self.matcher()?;
+
+        Ok(Searcher {
+            matcher,
+            invert_match: self.invert_match,
+            before_context: self.before_context,
+            after_context: self.after_context,
+            search_zip: self.search_zip,
+            records: self.records,
+        })
+    }
+
+    fn matcher(&self) -> Result<Box<dyn Matcher>, BuildError> {
+        if self.patterns.is_empty() {
+            return Err(BuildError::MissingPattern);
+        }
+
+        if let Some(max_distance) = self.fuzzy {
+            if let Some(pattern) = self.patterns.iter().find(|p| p.chars().count() > fuzzy::MAX_PATTERN_LEN) {
+                return Err(BuildError::PatternTooLong(pattern.clone()));
+            }
+            return Ok(Box::new(FuzzyMatcher::new(&self.patterns, max_distance, self.case_sensitive)));
+        }
+
+        // -w and -x are easiest to express as a regex, so in that case
+        // plain strings are escaped and matched with the regex engine as well.
+        if self.syntax == PatternSyntax::Fixed && !self.word_regexp && !self.line_regexp {
+            return Ok(if self.case_sensitive {
+                Box::new(LiteralMatcher::new(&self.patterns))
+            } else {
+                Box::new(CaseInsensitiveMatcher::new(&self.patterns))
+            });
+        }
+
+        let patterns: Vec<String> = self
+            .patterns
+            .iter()
+            .map(|pattern| match self.syntax {
+                PatternSyntax::Fixed => regex::escape(pattern),
+                PatternSyntax::Regex => pattern.clone(),
+            })
+            .map(|pattern| {
+                if self.line_regexp {
+                    format!("^(?:{})$", pattern)
+                } else if self.word_regexp {
+                    format!(r"\b(?:{})\b", pattern)
+                } else {
+                    pattern
+                }
+            })
+            .collect();
+
+        let matcher = match self.records {
+            RecordMode::Line => RegexMatcher::new(&patterns, !self.case_sensitive),
+            _ => RegexMatcher::multi_line(&patterns, !self.case_sensitive),
+        };
+        let matcher = matcher.map_err(BuildError::Regex)?;
+        Ok(Box::new(matcher))
+    }
+}
+
+impl Default for SearcherBuilder {
+    fn default() -> SearcherBuilder {
+        SearcherBuilder::new()
+    }
+}
+
+/// Searches sources, and hands the results to a Sink. Build one with Searcher::builder().
+///
+/// A Searcher can be shared between threads, to search several sources at the same time.
+pub struct Searcher {
+    matcher: Box<dyn Matcher>,
+    invert_match: bool,
+    before_context: usize,
+    after_context: usize,
+    search_zip: bool,
+    records: RecordMode,
+}
+
+impl Searcher {
+    pub fn builder() -> SearcherBuilder {
+        SearcherBuilder::new()
+    }
+
+    /// The matcher that was built from the patterns, to find or replace matches in a single line.
+    pub fn matcher(&self) -> &dyn Matcher {
+        self.matcher.as_ref()
+    }
+
+    /// Searches a file. With search_zip, the file extension is also used to detect compression.
+    /// Returns how many lines were selected.
+    pub fn search_path<S: Sink>(&self, path: &Path, sink: &mut S) -> Result<usize, S::Error> {
+        let reader = BufReader::new(File::open(path)?);
+        self.search_boxed(path, Box::new(reader), sink)
+    }
+
+    /// Searches anything that can be read line by line, like stdin.
+    pub fn search_reader<R: BufRead, S: Sink>(&self, reader: R, sink: &mut S) -> Result<usize, S::Error> {
+        self.search_boxed(Path::new(""), Box::new(reader), sink)
+    }
+
+    pub fn search_str<S: Sink>(&self, text: &str, sink: &mut S) -> Result<usize, S::Error> {
+        self.search_reader(text.as_bytes(), sink)
+    }
+
+    fn search_boxed<'a, S: Sink>(
+        &self,
+        path: &Path,
+        mut reader: Box<dyn BufRead + 'a>,
+        sink: &mut S,
+    ) -> Result<usize, S::Error> {
+        if self.search_zip {
+            reader = decompress::decompress(path, reader)?;
+        }
+
+        // Binary files are skipped, so searching a directory does not fill the terminal with
+        // garbage from images or compiled files. We only peek at the start of the input to decide.
+        if walk::is_binary(reader.fill_buf()?) {
+            return Ok(0);
+        }
+
+        sink.begin()?;
+
+        let matcher = self.matcher.as_ref();
+        let (before, after) = (self.before_context, self.after_context);
+        let emit = |entry: Entry<'_>| match entry {
+            Entry::Match(m) => sink.matched(&m),
+            Entry::Context(line) => sink.context(&line),
+            Entry::Separator => sink.separator(),
+        };
+
+        let selected = match self.records {
+            RecordMode::Line => search::search_reader(reader, matcher, self.invert_match, before, after, emit)?,
+            // A match may span any number of lines, so we need the whole source in memory.
+            records => {
+                let mut bytes = Vec::new();
+                reader.read_to_end(&mut bytes)?;
+                let content = String::from_utf8_lossy(&bytes);
+
+                // The records only know where they are in the converted content, but the
+                // offsets we print should be offsets in the file, just like for lines.
+                let offsets = search::OriginalOffsets::new(&bytes);
+                let mut emit = emit;
+                let emit = |entry: Entry<'_>| match entry {
+                    Entry::Match(mut m) => {
+                        m.line.offset = offsets.original(m.line.offset);
+                        emit(Entry::Match(m))
+                    }
+                    Entry::Context(mut line) => {
+                        line.offset = offsets.original(line.offset);
+                        emit(Entry::Context(line))
+                    }
+                    Entry::Separator => emit(Entry::Separator),
+                };
+                search::search_records(&content, matcher, records, self.invert_match, before, after, emit)?
+            }
+        };
+
+        sink.finish(selected)?;
+        Ok(selected)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POEM: &str = "\
+I'm nobody! Who are you?
+Are you nobody, too?
+Then there's a pair of us - don't tell!
+They'd banish us, you know.";
+
+    // Remembers everything, to check what a Searcher hands to its Sink.
+    #[derive(Default)]
+    struct Recorder {
+        events: Vec<String>,
+    }
+
+    impl Sink for Recorder {
+        type Error = io::Error;
+
+        fn matched(&mut self, m: &Match) -> io::Result<()> {
+            self.events.push(format!("{}:{}", m.line.number, m.line.text));
+            Ok(())
+        }
+
+        fn context(&mut self, line: &Line) -> io::Result<()> {
+            self.events.push(format!("{}-{}", line.number, line.text));
+            Ok(())
+        }
+
+        fn begin(&mut self) -> io::Result<()> {
+            self.events.push("begin".to_string());
+            Ok(())
+        }
+
+        fn finish(&mut self, selected: usize) -> io::Result<()> {
+            self.events.push(format!("finish {}", selected));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn searches_a_string_with_a_closure() {
+        let searcher = Searcher::builder().pattern("NOBODY").ignore_case(true).build().unwrap();
+        let mut numbers = Vec::new();
+
+        let selected = searcher
+            .search_str(POEM, &mut |m: &Match| {
+                numbers.push(m.line.number);
+                Ok(())
+            })
+            .unwrap();
+
+        assert_eq!(2, selected);
+        assert_eq!(vec![1, 2], numbers);
+    }
+
+    #[test]
+    fn sink_gets_context_begin_and_finish() {
+        let searcher = Searcher::builder()
+            .patterns(["pair", "banish"])
+            .syntax(PatternSyntax::Regex)
+            .invert_match(false)
+            .context(1, 0)
+            .build()
+            .unwrap();
+        let mut recorder = Recorder::default();
+        searcher.search_reader(io::Cursor::new(POEM), &mut recorder).unwrap();
+
+        assert_eq!(
+            vec![
+                "begin",
+                "2-Are you nobody, too?",
+                "3:Then there's a pair of us - don't tell!",
+                "4:They'd banish us, you know.",
+                "finish 2"
+            ],
+            recorder.events
+        );
+    }
+
+    #[test]
+    fn record_offsets_count_the_original_bytes() {
+        let searcher = Searcher::builder().pattern("b").records(RecordMode::Paragraph).build().unwrap();
+        let mut offsets = Vec::new();
+        searcher
+            .search_reader(&b"\xff\xfea\n\nb\n"[..], &mut |m: &Match| {
+                offsets.push(m.line.offset);
+                Ok(())
+            })
+            .unwrap();
+
+        // In the converted text, "b" starts at byte 9, but in the input it is byte 5.
+        assert_eq!(vec![5], offsets);
+    }
+
+    #[test]
+    fn build_errors() {
+        assert_eq!(Some(BuildError::MissingPattern), Searcher::builder().build().err());
+        assert!(matches!(
+            Searcher::builder().pattern("(").syntax(PatternSyntax::Regex).build(),
+            Err(BuildError::Regex(_))
+        ));
+        assert!(matches!(
+            Searcher::builder().pattern("x".repeat(65)).fuzzy(Some(1)).build(),
+            Err(BuildError::PatternTooLong(_))
+        ));
+    }
+}
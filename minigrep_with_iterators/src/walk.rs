@@ -0,0 +1,167 @@
+// Turns the paths from the command line into the list of files to search.
+//
+// Without -r, the paths are used as they are. With -r, we walk every directory
+// with the 'ignore' crate, which knows how to read .gitignore files, match globs
+// and stop at a maximum depth, so we don't have to write all of that ourselves.
+//
+// Unlike grep, we skip hidden files and directories (like .git or .env) by default, just like
+// ripgrep does. --hidden searches them as well.
+
+use std::path::PathBuf;
+
+use ignore::overrides::OverrideBuilder;
+use ignore::WalkBuilder;
+
+use crate::Config;
+
+pub type Paths = Box<dyn Iterator<Item = Result<PathBuf, ignore::Error>>>;
+
+/// Returns every file that should be searched, in a stable (sorted) order.
+///
+/// This fails up front if one of the --include or --exclude globs is invalid.
+/// Errors for single entries (like a directory we may not read) are returned
+/// by the iterator, so the caller can decide what to do with them.
+pub fn paths(config: &Config) -> Result<Paths, ignore::Error> {
+    if !config.recursive {
+        let paths: Vec<PathBuf> = config.paths.iter().map(PathBuf::from).collect();
+        return Ok(Box::new(paths.into_iter().map(Ok)));
+    }
+
+    // An override glob is a whitelist, and a glob starting with '!' is a blacklist.
+    // Globs without a slash are matched against the file name, so *.rs matches in every directory.
+    let mut overrides = OverrideBuilder::new(".");
+    for glob in &config.include {
+        overrides.add(glob)?;
+    }
+    for glob in &config.exclude {
+        overrides.add(&format!("!{}", glob))?;
+    }
+
+    let mut roots = config.paths.iter();
+    let mut builder = WalkBuilder::new(roots.next().map(String::as_str).unwrap_or("."));
+    for root in roots {
+        builder.add(root);
+    }
+
+    builder
+        .overrides(overrides.build()?)
+        .max_depth(config.max_depth)
+        .hidden(!config.hidden)
+        // Read .gitignore files, even if the directory is not inside of a git repository.
+        .require_git(false)
+        .sort_by_file_name(|a, b| a.cmp(b));
+
+    if config.no_ignore {
+        builder
+            .ignore(false)
+            .git_ignore(false)
+            .git_global(false)
+            .git_exclude(false)
+            .parents(false);
+    }
+
+    let files = builder.build().filter_map(|entry| match entry {
+        Ok(entry) if entry.file_type().is_some_and(|t| t.is_file()) => Some(Ok(entry.into_path())),
+        Ok(_) => None,
+        Err(err) => Some(Err(err)),
+    });
+
+    Ok(Box::new(files))
+}
+
+/// A file is treated as binary if it contains a NUL byte near the start, just like grep does.
+pub fn is_binary(bytes: &[u8]) -> bool {
+    bytes.iter().take(8 * 1024).any(|&b| b == 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::parse;
+    use std::fs;
+
+    fn found(config: &Config, root: &std::path::Path) -> Vec<String> {
+        paths(config)
+            .unwrap()
+            .map(|path| {
+                let path = path.unwrap();
+                path.strip_prefix(root).unwrap().to_string_lossy().replace('\\', "/")
+            })
+            .collect()
+    }
+
+    // src/lib.rs, src/main.rs, src/deep/er/file.rs, notes.txt, target/out.rs (ignored in .gitignore)
+    fn tree() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("src/deep/er")).unwrap();
+        fs::create_dir_all(root.join("target")).unwrap();
+        fs::write(root.join(".gitignore"), "target/\n").unwrap();
+        fs::write(root.join("notes.txt"), "to do").unwrap();
+        fs::write(root.join("src/lib.rs"), "to").unwrap();
+        fs::write(root.join("src/main.rs"), "to").unwrap();
+        fs::write(root.join("src/deep/er/file.rs"), "to").unwrap();
+        fs::write(root.join("target/out.rs"), "to").unwrap();
+        dir
+    }
+
+    #[test]
+    fn walks_recursively_and_respects_gitignore() {
+        let dir = tree();
+        let root = dir.path().to_str().unwrap();
+
+        assert_eq!(
+            vec!["notes.txt", "src/deep/er/file.rs", "src/lib.rs", "src/main.rs"],
+            found(&parse(&["-r", "to", root]).unwrap(), dir.path())
+        );
+
+        let all = found(&parse(&["-r", "--no-ignore", "to", root]).unwrap(), dir.path());
+        assert!(all.contains(&"target/out.rs".to_string()));
+    }
+
+    #[test]
+    fn hidden_files_only_with_hidden() {
+        let dir = tree();
+        let root = dir.path().to_str().unwrap();
+        fs::create_dir_all(dir.path().join(".config")).unwrap();
+        fs::write(dir.path().join(".config/x"), "to").unwrap();
+        fs::write(dir.path().join(".env"), "to").unwrap();
+
+        let visible = found(&parse(&["-r", "--no-ignore", "to", root]).unwrap(), dir.path());
+        assert!(!visible.iter().any(|path| path.starts_with('.')));
+
+        let all = found(&parse(&["-r", "--hidden", "to", root]).unwrap(), dir.path());
+        assert_eq!(vec![".config/x", ".env", ".gitignore", "notes.txt"], all[..4]);
+    }
+
+    #[test]
+    fn include_exclude_and_max_depth() {
+        let dir = tree();
+        let root = dir.path().to_str().unwrap();
+
+        assert_eq!(
+            vec!["src/deep/er/file.rs", "src/lib.rs", "src/main.rs"],
+            found(&parse(&["-r", "--include=*.rs", "to", root]).unwrap(), dir.path())
+        );
+        assert_eq!(
+            vec!["notes.txt", "src/deep/er/file.rs", "src/lib.rs"],
+            found(&parse(&["-r", "--exclude", "main.rs", "to", root]).unwrap(), dir.path())
+        );
+        assert_eq!(
+            vec!["notes.txt", "src/lib.rs", "src/main.rs"],
+            found(&parse(&["-r", "--max-depth", "2", "to", root]).unwrap(), dir.path())
+        );
+    }
+
+    #[test]
+    fn without_recursion_paths_are_used_as_given() {
+        let paths: Vec<PathBuf> = paths(&parse(&["to", "a.txt", "b"]).unwrap()).unwrap().map(Result::unwrap).collect();
+        assert_eq!(vec![PathBuf::from("a.txt"), PathBuf::from("b")], paths);
+    }
+
+    #[test]
+    fn detects_binary_files() {
+        assert!(is_binary(b"ELF\0\x01\x02"));
+        assert!(!is_binary("plain text, ünïcode".as_bytes()));
+    }
+}
@@ -0,0 +1,40 @@
+// Runs the minigrep binary with input on stdin, which the unit tests can't provide.
+
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+fn minigrep(args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_minigrep"))
+        .args(args)
+        .env_remove("CASE_INSENSITIVE")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    child.stdin.take().unwrap().write_all(stdin.as_bytes()).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn searches_stdin() {
+    let output = minigrep(&["-n", "to"], "Are you nobody, too?\nI'm nobody!\n");
+    assert_eq!(Some(0), output.status.code());
+    assert_eq!("1:Are you nobody, too?\n", String::from_utf8_lossy(&output.stdout));
+}
+
+#[test]
+fn sorts_stdin_by_score() {
+    let output = minigrep(&["--fuzzy", "1", "--sort=score", "connection"], "conection lost\nconnection refused\n");
+    assert_eq!("", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(Some(0), output.status.code());
+    assert_eq!("connection refused\nconection lost\n", String::from_utf8_lossy(&output.stdout));
+}
+
+#[test]
+fn replaces_a_multiline_match_with_its_line_ending() {
+    let output = minigrep(&["-U", "-E", "--replace=X", r"boom\n"], "INFO start\nERROR boom\nINFO done\n");
+    assert_eq!(Some(0), output.status.code());
+    assert_eq!("ERROR X\n", String::from_utf8_lossy(&output.stdout));
+}
@@ -0,0 +1,273 @@
+// The State pattern the way it is done in OOP languages: every state is an object behind a
+// Box<dyn State>, and decides itself what every action does.
+//
+// This is how Post worked before it was built on a Machine (see workflow() in lib.rs). It is kept
+// here as the example of the pattern, with the same states and rules as the workflow, but without
+// the users and the history. A test below checks that both still agree on every action.
+
+use crate::{Action, StateName, TransitionError};
+
+pub struct Post {
+    state: Option<Box<dyn State>>,
+    content: String,
+}
+
+impl Post {
+    pub fn new() -> Post {
+        Post {
+            state: Some(Box::new(Draft {})),
+            content: String::new(),
+        }
+    }
+
+    // The state decides whether we may add text, but the Post still does the adding itself,
+    // because the state objects don't know anything about the content.
+    pub fn add_text(&mut self, text: &str) -> Result<(), TransitionError> {
+        if !self.state.as_ref().unwrap().can_add_text() {
+            return Err(TransitionError { state: self.state(), action: Action::AddText });
+        }
+        self.content.push_str(text);
+        Ok(())
+    }
+
+    pub fn state(&self) -> StateName {
+        self.state.as_ref().unwrap().name()
+    }
+
+    pub fn content(&self) -> &str {
+        // We want a reference to the state object. We do not need to own it as below, as we are not going
+        // to change it. Also, with unwrap(), we get back a &Box<Option<dyn State>>, but because of
+        // deref coercion, we will be able to call .content() on the state.
+        self.state.as_ref().unwrap().content(self)
+    }
+
+    // As we can see, these are always going to be the same, no matter what state we currently are in,
+    // because each state is reponsible for its own rules, that govern what happens when we call
+    // request_review() on that particular state object.
+    pub fn request_review(&mut self) -> Result<(), TransitionError> {
+        self.transition(Action::RequestReview, |state| state.request_review())
+    }
+
+    pub fn approve(&mut self) -> Result<(), TransitionError> {
+        self.transition(Action::Approve, |state| state.approve())
+    }
+
+    pub fn reject(&mut self) -> Result<(), TransitionError> {
+        self.transition(Action::Reject, |state| state.reject())
+    }
+
+    pub fn unpublish(&mut self) -> Result<(), TransitionError> {
+        self.transition(Action::Unpublish, |state| state.unpublish())
+    }
+
+    fn transition(&mut self, action: Action, change: impl FnOnce(Box<dyn State>) -> Transition) -> Result<(), TransitionError> {
+        // This is why we needed to use Option<> for the state. We take ownership of the value (move it)
+        // from the Option<>, and set our state to the result of the call.
+        // So we are moving the state outside of our Post struct, and Rust does not allow empty fields in Structs.
+        // That is why we need to use Option<>.
+        // The call will be different, based on whatever the state currently is.
+        //
+        // Therefore, self.state is None. So we reassign it to Some().
+        // The call will consume the old state, invalidating it, and return a new state. If the action
+        // is not allowed in this state, we get the old state back instead, and put it back.
+        let state = self.state.take().unwrap();
+        match change(state) {
+            Ok(state) => {
+                self.state = Some(state);
+                Ok(())
+            }
+            Err(state) => {
+                let err = TransitionError { state: state.name(), action };
+                self.state = Some(state);
+                Err(err)
+            }
+        }
+    }
+}
+
+impl Default for Post {
+    fn default() -> Post {
+        Post::new()
+    }
+}
+
+// The result of a transition: Ok with the new state, or Err with the old state,
+// if the action is not allowed in that state.
+type Transition = Result<Box<dyn State>, Box<dyn State>>;
+
+pub trait State {
+    // We take ownership of a Box containing self, but we don't use self.
+    // So we are essentially invalidating the old state, and returning a new state.
+    fn request_review(self: Box<Self>) -> Transition;
+    fn approve(self: Box<Self>) -> Transition;
+    fn reject(self: Box<Self>) -> Transition;
+    fn unpublish(self: Box<Self>) -> Transition;
+    fn content<'a>(&self, _post: &'a Post) -> &'a str {
+        ""
+    }
+    // Text can only be added while the post is a draft, so only Draft says yes.
+    fn can_add_text(&self) -> bool {
+        false
+    }
+    // Every state knows its own name, so we can tell which state the post is in.
+    fn name(&self) -> StateName;
+}
+
+struct Draft {}
+
+impl State for Draft {
+    fn request_review(self: Box<Self>) -> Transition {
+        Ok(Box::new(PendingReview { approvals: 0 }))
+    }
+
+    fn approve(self: Box<Self>) -> Transition {
+        Err(self)
+    }
+
+    // There is nothing to reject yet.
+    fn reject(self: Box<Self>) -> Transition {
+        Err(self)
+    }
+
+    fn unpublish(self: Box<Self>) -> Transition {
+        Err(self)
+    }
+
+    fn can_add_text(&self) -> bool {
+        true
+    }
+
+    fn name(&self) -> StateName {
+        StateName::Draft
+    }
+}
+
+// A post has to be approved by two reviewers, before it gets published.
+const APPROVALS_NEEDED: u32 = 2;
+
+struct PendingReview {
+    approvals: u32,
+}
+
+impl State for PendingReview {
+    // If we call request_review() while already being in PendingReview state,
+    // then we do not want to do anything, so we give back the state we are in.
+    fn request_review(self: Box<Self>) -> Transition {
+        Err(self)
+    }
+
+    fn approve(self: Box<Self>) -> Transition {
+        let approvals = self.approvals + 1;
+        match approvals >= APPROVALS_NEEDED {
+            true => Ok(Box::new(Published {})),
+            false => Ok(Box::new(PendingReview { approvals })),
+        }
+    }
+
+    // The author gets the post back, and it needs all of its approvals again after the changes.
+    fn reject(self: Box<Self>) -> Transition {
+        Ok(Box::new(Draft {}))
+    }
+
+    // Only a published post can be unpublished.
+    fn unpublish(self: Box<Self>) -> Transition {
+        Err(self)
+    }
+
+    fn name(&self) -> StateName {
+        StateName::PendingReview
+    }
+}
+
+struct Published {}
+
+impl State for Published {
+    fn request_review(self: Box<Self>) -> Transition {
+        Err(self)
+    }
+
+    fn approve(self: Box<Self>) -> Transition {
+        Err(self)
+    }
+
+    fn reject(self: Box<Self>) -> Transition {
+        Err(self)
+    }
+
+    fn unpublish(self: Box<Self>) -> Transition {
+        Ok(Box::new(Draft {}))
+    }
+
+    // Function takes two references, and returns a reference, so we need to tell the compiler
+    // the relationship between these 3 references. Here, the lifetime of our return type, is tied
+    // to the lifetime of post, so we tell the compiler this.
+    fn content<'a>(&self, post: &'a Post) -> &'a str {
+        &post.content
+    }
+
+    fn name(&self) -> StateName {
+        StateName::Published
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::workflow;
+
+    #[test]
+    fn needs_two_approvals() {
+        let mut post = Post::new();
+        post.add_text("I ate a salad for lunch today").unwrap();
+        post.request_review().unwrap();
+
+        post.approve().unwrap();
+        assert_eq!(StateName::PendingReview, post.state());
+        assert_eq!("", post.content());
+
+        post.approve().unwrap();
+        assert_eq!("I ate a salad for lunch today", post.content());
+        assert!(post.add_text(" and a steak").is_err());
+    }
+
+    // The state objects and the workflow are two ways to write the same rules,
+    // so every action should be allowed by both, or refused by both.
+    #[test]
+    fn same_rules_as_the_workflow() {
+        let actions = [
+            Action::Approve,
+            Action::AddText,
+            Action::RequestReview,
+            Action::AddText,
+            Action::Approve,
+            Action::Reject,
+            Action::RequestReview,
+            Action::Approve,
+            Action::Approve,
+            Action::Reject,
+            Action::Unpublish,
+            Action::Unpublish,
+        ];
+
+        let mut post = Post::new();
+        let (mut state, mut approvers) = (workflow().initial(), Vec::new());
+        for action in actions {
+            let result = match action {
+                Action::AddText => post.add_text("text"),
+                Action::RequestReview => post.request_review(),
+                Action::Approve => post.approve(),
+                Action::Reject => post.reject(),
+                Action::Unpublish => post.unpublish(),
+            };
+
+            let fired = workflow().fire(state, action, &mut approvers);
+            // The workflow only counts the approvers, so their names don't matter here.
+            if fired.is_some() && action == Action::Approve {
+                approvers.push(String::new());
+            }
+            assert_eq!(fired.is_some(), result.is_ok(), "{:?} in {:?}", action, state);
+            state = fired.unwrap_or(state);
+            assert_eq!(state, post.state());
+        }
+    }
+}
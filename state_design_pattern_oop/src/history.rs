@@ -0,0 +1,146 @@
+// The history of a post: who did what with it, and when.
+//
+// Every action that is not refused is written down as an Event. Nothing is ever taken out of the
+// history or changed in it, we only add to the end. Because an event says everything about the
+// action (even the text that was added), we can also go the other way, and build the post again
+// by doing every event once more, in order. That is how posts are loaded (see the Deserialize impl
+// of Post), so the history is what is really stored, and the state and content follow from it.
+
+use std::error::Error;
+use std::fmt;
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Action, Post, StateName, TransitionError};
+
+/// One action that was done with a post.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Event {
+    pub action: Action,
+    pub from: StateName,
+    pub to: StateName,
+    /// Who did it, like "alice".
+    pub actor: String,
+    pub timestamp: SystemTime,
+    /// What a reviewer said when approving or rejecting.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+    /// The text that was added, for AddText.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+}
+
+impl Post {
+    /// Everything that was done with the post, oldest first.
+    pub fn history(&self) -> impl Iterator<Item = &Event> {
+        self.history.iter()
+    }
+
+    /// Builds a post by doing every event again. Fails if an event is not possible, or does not
+    /// end in the state it says, because then the events don't belong to one post.
+    pub fn replay(events: impl IntoIterator<Item = Event>) -> Result<Post, ReplayError> {
+        let mut post = Post::new();
+
+        for (index, event) in events.into_iter().enumerate() {
+            if post.state() != event.from {
+                return Err(ReplayError::WrongState { index, expected: event.from, found: post.state() });
+            }
+            post.apply(event.action, &event.actor, event.text.as_deref())
+                .map_err(|err| ReplayError::Refused { index, err })?;
+            if post.state() != event.to {
+                return Err(ReplayError::WrongState { index, expected: event.to, found: post.state() });
+            }
+
+            // We keep the event as it was, with its actor and timestamp, instead of making a new one.
+            post.history.push(event);
+        }
+
+        Ok(post)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ReplayError {
+    /// The event at `index` was refused by the post.
+    Refused { index: usize, err: TransitionError },
+    /// Before or after the event at `index`, the post was not in the state the event says.
+    WrongState { index: usize, expected: StateName, found: StateName },
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReplayError::Refused { index, err } => write!(f, "event {}: {}", index, err),
+            ReplayError::WrongState { index, expected, found } => write!(
+                f,
+                "event {}: the post should be in state {}, but it is in state {}",
+                index,
+                expected.as_str(),
+                found.as_str()
+            ),
+        }
+    }
+}
+
+impl Error for ReplayError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{alice, bob, carol};
+
+    fn published() -> Post {
+        let mut post = Post::new();
+        post.add_text(&alice(), "I ate a salad").unwrap();
+        post.request_review(&alice()).unwrap();
+        post.approve(&bob(), Some("Looks good")).unwrap();
+        post.approve(&carol(), None).unwrap();
+        post
+    }
+
+    #[test]
+    fn records_every_action_in_order() {
+        let mut post = published();
+        // A refused action is not in the history.
+        assert!(post.reject(&bob(), None).is_err());
+
+        let events: Vec<&Event> = post.history().collect();
+        let actions: Vec<Action> = events.iter().map(|event| event.action).collect();
+        assert_eq!(vec![Action::AddText, Action::RequestReview, Action::Approve, Action::Approve], actions);
+
+        assert_eq!("alice", events[0].actor);
+        assert_eq!(Some("I ate a salad"), events[0].text.as_deref());
+        assert_eq!((StateName::Draft, StateName::PendingReview), (events[1].from, events[1].to));
+        assert_eq!(Some("Looks good"), events[2].comment.as_deref());
+        assert_eq!((StateName::PendingReview, StateName::Published), (events[3].from, events[3].to));
+        assert!(events.windows(2).all(|pair| pair[0].timestamp <= pair[1].timestamp));
+    }
+
+    #[test]
+    fn replay_builds_the_same_post() {
+        let post = published();
+        let replayed = Post::replay(post.history().cloned()).unwrap();
+
+        assert_eq!(StateName::Published, replayed.state());
+        assert_eq!("I ate a salad", replayed.content());
+        assert!(post.history().eq(replayed.history()));
+    }
+
+    #[test]
+    fn replay_refuses_a_history_that_does_not_add_up() {
+        let mut events: Vec<Event> = published().history().cloned().collect();
+
+        // Without the second approval, the last event should have published the post.
+        events.remove(2);
+        assert_eq!(
+            Err(ReplayError::WrongState { index: 2, expected: StateName::Published, found: StateName::PendingReview }),
+            Post::replay(events.clone()).map(|_| ())
+        );
+
+        // Without the review request, the post is still a draft when it is approved.
+        events.remove(1);
+        let err = Post::replay(events).map(|_| ()).unwrap_err();
+        assert_eq!("event 1: the post should be in state PendingReview, but it is in state Draft", err.to_string());
+    }
+}
@@ -1,105 +1,406 @@
+use std::borrow::Cow;
+use std::error::Error;
+use std::fmt;
+use std::sync::OnceLock;
+use std::time::SystemTime;
+
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+pub mod dyn_state;
+pub mod history;
+pub mod permissions;
+pub mod repository;
+
+pub use history::{Event, ReplayError};
+pub use permissions::{PermissionError, Role, User};
+use state_machine::Machine;
+
 pub struct Post {
-    state: Option<Box<dyn State>>,
+    state: StateName,
+    // Who approved the post since it was sent for review. This is the context of the workflow,
+    // which its rules read and change.
+    approvers: Vec<String>,
     content: String,
+    // Everything that was done with the post, oldest first. We only ever add to it.
+    history: Vec<Event>,
 }
 
 impl Post {
     pub fn new() -> Post {
         Post {
-            state: Some(Box::new(Draft {})),
+            state: workflow().initial(),
+            approvers: Vec::new(),
             content: String::new(),
+            history: Vec::new(),
         }
     }
 
-    // This function is not part of the state pattern -> We do not care about the state
-    // when calling this function.
-    pub fn add_text(&mut self, text: &str) {
-        self.content.push_str(text);
+    // Every action says who does it, so we can check whether they may (see permissions.rs),
+    // and so it can be written down in the history.
+    pub fn add_text(&mut self, user: &User, text: &str) -> Result<(), PostError> {
+        self.record(Action::AddText, user, Some(text), None)
+    }
+
+    /// Whoever did the first action with the post. None for a new post.
+    pub fn author(&self) -> Option<&str> {
+        self.history.first().map(|event| event.actor.as_str())
+    }
+
+    pub fn state(&self) -> StateName {
+        self.state
+    }
+
+    /// The name of the current state, like "PendingReview", e.g. to show why an action was refused.
+    pub fn state_name(&self) -> &'static str {
+        self.state().as_str()
     }
 
+    /// Returns an empty string until the post is published. Use state() to tell the two apart.
     pub fn content(&self) -> &str {
-        // We want a reference to the state object. We do not need to own it as below, as we are not going
-        // to change it. Also, with unwrap(), we get back a &Box<Option<dyn State>>, but because of
-        // deref coercion, we will be able to call .content() on the state.
-        self.state.as_ref().unwrap().content(self)
-    }
-
-    // As we can see, this method is always going to be the same, no matter what state we currently are in,
-    // because each state is reponsible for its own rules, that govern what happens when we call request_review()
-    // on that particular state object.
-    pub fn request_review(&mut self) {
-        // This is why we needed to use Option<> for the state. We take ownership of the value (move it)
-        // from the Option<>, and set our state to the result of the request_review() call.
-        // So we are moving the state outside of our Post struct, and Rust does not allow empty fields in Structs.
-        // That is why we need to use Option<>.
-        // The call will be different, based on whatever the state currently is.
-        //
-        // Therefore, self.state is None. So we reassign it to Some().
-        // request_review() will consume the old state, invalidating it, and return a new state.
-        if let Some(state) = self.state.take() {
-            self.state = Some(state.request_review())
+        match self.state {
+            StateName::Published => &self.content,
+            _ => "",
         }
     }
 
-    // Same as above
-    pub fn approve(&mut self) {
-        if let Some(state) = self.state.take() {
-            self.state = Some(state.approve())
+    pub fn request_review(&mut self, user: &User) -> Result<(), PostError> {
+        self.record(Action::RequestReview, user, None, None)
+    }
+
+    // A reviewer can say why they approve (or reject) the post.
+    pub fn approve(&mut self, user: &User, comment: Option<&str>) -> Result<(), PostError> {
+        self.record(Action::Approve, user, None, comment)
+    }
+
+    // A reviewer sends the post back to the author, who can then change it.
+    pub fn reject(&mut self, user: &User, comment: Option<&str>) -> Result<(), PostError> {
+        self.record(Action::Reject, user, None, comment)
+    }
+
+    // An admin takes a published post down again. It becomes a draft, which the author can fix.
+    pub fn unpublish(&mut self, user: &User, comment: Option<&str>) -> Result<(), PostError> {
+        self.record(Action::Unpublish, user, None, comment)
+    }
+
+    // Does the action, and writes it down in the history. A refused action is not written down.
+    // Only the name of the user is kept. So when the history is replayed, the permissions are
+    // not checked again: they were checked when the action was done.
+    fn record(&mut self, action: Action, user: &User, text: Option<&str>, comment: Option<&str>) -> Result<(), PostError> {
+        permissions::check(user, action, self.author(), &self.approvers)?;
+
+        let from = self.state();
+        self.apply(action, user.name(), text)?;
+
+        self.history.push(Event {
+            action,
+            from,
+            to: self.state(),
+            actor: user.name().to_string(),
+            timestamp: SystemTime::now(),
+            comment: comment.map(String::from),
+            text: text.map(String::from),
+        });
+        Ok(())
+    }
+
+    // Does the action, without writing it down. Replaying the history uses this as well.
+    fn apply(&mut self, action: Action, actor: &str, text: Option<&str>) -> Result<(), TransitionError> {
+        // The workflow decides what the action leads to, and we only keep the result. If it
+        // refuses the action, neither the state nor the approvers change.
+        let Some(state) = workflow().fire(self.state, action, &mut self.approvers) else {
+            return Err(TransitionError { state: self.state, action });
+        };
+        self.state = state;
+
+        // The workflow doesn't know anything about the content or the users, so adding the text,
+        // and writing down who approved, stays our job.
+        match action {
+            Action::AddText => self.content.push_str(text.unwrap_or_default()),
+            Action::Approve => self.approvers.push(actor.to_string()),
+            _ => {}
         }
+        Ok(())
     }
 }
 
-pub trait State {
-    // We take ownership of a Box containing self, but we don't use self.
-    // So we are essentially invalidating the old state, and returning a new state.
-    fn request_review(self: Box<Self>) -> Box<dyn State>;
-    fn approve(self: Box<Self>) -> Box<dyn State>;
-    fn content<'a>(&self, post: &'a Post) -> &'a str {
-        ""
+// A post has to be approved by two different reviewers, before it gets published.
+// That they are different is checked in permissions.rs.
+const APPROVALS_NEEDED: usize = 2;
+
+/// The rules of every post: which action leads from which state to which other state.
+/// The context is the list of reviewers who approved the post so far.
+pub fn workflow() -> &'static Machine<StateName, Action, Vec<String>> {
+    // The rules are the same for every post, so we only build them once.
+    static WORKFLOW: OnceLock<Machine<StateName, Action, Vec<String>>> = OnceLock::new();
+
+    WORKFLOW.get_or_init(|| {
+        Machine::<_, _, Vec<String>>::builder(StateName::Draft)
+            .state(StateName::PendingReview)
+            .state(StateName::Published)
+            // Text can only be added while the post is a draft.
+            .rule(StateName::Draft, Action::AddText, StateName::Draft)
+            .rule(StateName::Draft, Action::RequestReview, StateName::PendingReview)
+            // The last approval publishes the post, every other one is only written down.
+            .rule(StateName::PendingReview, Action::Approve, StateName::Published)
+            .guard("last approval", |approvers| approvers.len() + 1 >= APPROVALS_NEEDED)
+            .rule(StateName::PendingReview, Action::Approve, StateName::PendingReview)
+            // The author gets the post back, and it needs all of its approvals again after the changes.
+            .rule(StateName::PendingReview, Action::Reject, StateName::Draft)
+            .effect(|approvers| approvers.clear())
+            .rule(StateName::Published, Action::Unpublish, StateName::Draft)
+            .effect(|approvers| approvers.clear())
+            .build()
+            .expect("the blog workflow is valid")
+    })
+}
+
+/// Everything that can be done with a post.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Action {
+    AddText,
+    RequestReview,
+    Approve,
+    Reject,
+    Unpublish,
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let action = match self {
+            Action::AddText => "add text to",
+            Action::RequestReview => "request a review of",
+            Action::Approve => "approve",
+            Action::Reject => "reject",
+            Action::Unpublish => "unpublish",
+        };
+        write!(f, "{}", action)
     }
 }
 
-struct Draft {}
+/// An action that is not allowed in the state the post is in. The post did not change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransitionError {
+    pub state: StateName,
+    pub action: Action,
+}
 
-impl State for Draft {
-    fn request_review(self: Box<Self>) -> Box<dyn State> {
-        Box::new(PendingReview {})
+impl fmt::Display for TransitionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "can't {} a post in state {}", self.action, self.state.as_str())
     }
+}
+
+impl Error for TransitionError {}
+
+/// Why an action was refused: either the post is in the wrong state, or the user may not do it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PostError {
+    Transition(TransitionError),
+    Permission(PermissionError),
+}
 
-    fn approve(self: Box<Self>) -> Box<dyn State> {
-        self
+impl fmt::Display for PostError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PostError::Transition(err) => write!(f, "{}", err),
+            PostError::Permission(err) => write!(f, "{}", err),
+        }
     }
 }
 
-struct PendingReview {}
+impl Error for PostError {}
 
-impl State for PendingReview {
-    // If we call request_review() while already being in PendingReview state,
-    // then we do not want to do anything, so we just return the state we are in.
-    fn request_review(self: Box<Self>) -> Box<dyn State> {
-        self
+impl From<TransitionError> for PostError {
+    fn from(err: TransitionError) -> PostError {
+        PostError::Transition(err)
     }
+}
 
-    fn approve(self: Box<Self>) -> Box<dyn State> {
-        Box::new(Published {})
+impl From<PermissionError> for PostError {
+    fn from(err: PermissionError) -> PostError {
+        PostError::Permission(err)
     }
 }
 
-struct Published {}
+impl Default for Post {
+    fn default() -> Post {
+        Post::new()
+    }
+}
 
-impl State for Published {
-    fn request_review(self: Box<Self>) -> Box<dyn State> {
-        self
+// We don't save the state and the content themselves. Instead, we save the history of the post, and load it by doing
+// everything in the history again (see Post::replay()). So the history is the source of truth:
+// the state and the content are only what the history adds up to. On disk, a post looks like this:
+//
+//      {"events":[{"action":"AddText","from":"Draft","to":"Draft","actor":"alice",
+//          "timestamp":{"secs_since_epoch":1700000000,"nanos_since_epoch":0},"text":"I ate a salad"}, ...]}
+#[derive(Serialize, Deserialize)]
+struct StoredPost<'a> {
+    events: Cow<'a, [Event]>,
+}
+
+impl Serialize for Post {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        StoredPost { events: Cow::Borrowed(&self.history) }.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Post {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Post, D::Error> {
+        let stored = StoredPost::deserialize(deserializer)?;
+        Post::replay(stored.events.into_owned()).map_err(D::Error::custom)
+    }
+}
+
+/// Every state a post can be in. Unlike the state objects of dyn_state.rs, names can be compared, and saved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum StateName {
+    Draft,
+    PendingReview,
+    Published,
+}
+
+impl StateName {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StateName::Draft => "Draft",
+            StateName::PendingReview => "PendingReview",
+            StateName::Published => "Published",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The users of the tests in every module: alice writes posts, and bob and carol review them.
+    pub(crate) fn alice() -> User {
+        User::new("alice", &[Role::Author])
+    }
+
+    pub(crate) fn bob() -> User {
+        User::new("bob", &[Role::Reviewer])
+    }
+
+    pub(crate) fn carol() -> User {
+        User::new("carol", &[Role::Reviewer])
     }
 
-    fn approve(self: Box<Self>) -> Box<dyn State> {
-        self
+    fn pending_review(text: &str) -> Post {
+        let mut post = Post::new();
+        post.add_text(&alice(), text).unwrap();
+        post.request_review(&alice()).unwrap();
+        post
+    }
+
+    #[test]
+    fn needs_two_approvals() {
+        let mut post = pending_review("I ate a salad for lunch today");
+
+        post.approve(&bob(), None).unwrap();
+        assert_eq!(StateName::PendingReview, post.state());
+        assert_eq!("", post.content());
+
+        // The second approval has to come from another reviewer.
+        let err = post.approve(&bob(), None).unwrap_err();
+        assert_eq!(PostError::Permission(PermissionError::AlreadyApproved { user: "bob".to_string() }), err);
+        assert_eq!(StateName::PendingReview, post.state());
+
+        post.approve(&carol(), None).unwrap();
+        assert_eq!(StateName::Published, post.state());
+        assert_eq!("I ate a salad for lunch today", post.content());
+    }
+
+    #[test]
+    fn reject_goes_back_to_draft() {
+        let mut post = pending_review("I ate a salad");
+        post.approve(&bob(), None).unwrap();
+        post.reject(&bob(), None).unwrap();
+        assert_eq!(StateName::Draft, post.state());
+
+        // The approval from before the rejection does not count anymore, so bob can approve again.
+        post.add_text(&alice(), " for lunch").unwrap();
+        post.request_review(&alice()).unwrap();
+        post.approve(&bob(), None).unwrap();
+        assert_eq!(StateName::PendingReview, post.state());
+        post.approve(&carol(), None).unwrap();
+        assert_eq!("I ate a salad for lunch", post.content());
+    }
+
+    #[test]
+    fn text_can_only_be_added_to_a_draft() {
+        let mut post = pending_review("I ate a salad");
+        assert_eq!(
+            Err(PostError::Transition(TransitionError { state: StateName::PendingReview, action: Action::AddText })),
+            post.add_text(&alice(), " and a steak")
+        );
+
+        post.approve(&bob(), None).unwrap();
+        post.approve(&carol(), None).unwrap();
+        assert_eq!("I ate a salad", post.content());
+    }
+
+    #[test]
+    fn refused_actions_say_why() {
+        let mut post = Post::new();
+        let err = post.approve(&bob(), None).unwrap_err();
+        assert_eq!(PostError::Transition(TransitionError { state: StateName::Draft, action: Action::Approve }), err);
+        assert_eq!("can't approve a post in state Draft", err.to_string());
+
+        post.request_review(&alice()).unwrap();
+        post.approve(&bob(), None).unwrap();
+        post.approve(&carol(), None).unwrap();
+        assert_eq!("Published", post.state_name());
+
+        // A refused action leaves the post as it was.
+        assert!(matches!(
+            post.reject(&bob(), None),
+            Err(PostError::Transition(TransitionError { action: Action::Reject, .. }))
+        ));
+        assert_eq!("can't request a review of a post in state Published", post.request_review(&alice()).unwrap_err().to_string());
+        assert_eq!("Published", post.state_name());
+    }
+
+    // If the workflow changes, these have to change with it. Then the diff shows how it changed.
+    #[test]
+    fn workflow_as_dot() {
+        assert_eq!(
+            r#"digraph "Post" {
+    "__start" [shape=point];
+    "__start" -> "Draft";
+    "Draft" [shape=circle];
+    "PendingReview" [shape=circle];
+    "Published" [shape=circle];
+    "Draft" -> "Draft" [label="AddText"];
+    "Draft" -> "PendingReview" [label="RequestReview"];
+    "PendingReview" -> "Published" [label="Approve [last approval]"];
+    "PendingReview" -> "PendingReview" [label="Approve"];
+    "PendingReview" -> "Draft" [label="Reject"];
+    "Published" -> "Draft" [label="Unpublish"];
+}
+"#,
+            workflow().to_dot("Post")
+        );
     }
 
-    // Function takes two references, and returns a reference, so we need to tell the compiler
-    // the relationship between these 3 references. Here, the lifetime of our return type, is tied
-    // to the lifetime of post, so we tell the compiler this.
-    fn content<'a>(&self, post: &'a Post) -> &'a str {
-        &post.content
+    #[test]
+    fn workflow_as_mermaid() {
+        assert_eq!(
+            r#"stateDiagram-v2
+    state "Draft" as s0
+    state "PendingReview" as s1
+    state "Published" as s2
+    [*] --> s0
+    s0 --> s0: AddText
+    s0 --> s1: RequestReview
+    s1 --> s2: Approve [last approval]
+    s1 --> s1: Approve
+    s1 --> s0: Reject
+    s2 --> s0: Unpublish
+"#,
+            workflow().to_mermaid()
+        );
     }
 }
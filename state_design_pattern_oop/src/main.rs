@@ -8,18 +8,66 @@ instead.
 Another downside is duplication.
 
 So by implementing the State pattern exactly as in OOP, we are not taking full advantage of Rust.
+
+The state objects are still in dyn_state.rs, as the example of this pattern. Post itself uses a Machine
+(from the state_machine crate) instead: the states and actions are plain enums, and the rules between them
+are declared in one place, in workflow(). Adding the "schedule" state means adding a state and two rules
+there, and nothing else has to change.
  */
-use state_design_pattern_oop::Post;
+use std::env;
+
+use state_design_pattern_oop::{workflow, Post, Role, User};
 
 fn main() {
+    // `cargo run -- dot` or `cargo run -- mermaid` prints the workflow as a diagram, instead of the example.
+    let diagram = match env::args().nth(1).as_deref() {
+        Some("dot") => Some(workflow().to_dot("Post")),
+        Some("mermaid") => Some(workflow().to_mermaid()),
+        _ => None,
+    };
+    if let Some(diagram) = diagram {
+        print!("{}", diagram);
+        return;
+    }
+
+    let alice = User::new("alice", &[Role::Author]);
+    let bob = User::new("bob", &[Role::Reviewer]);
+    let carol = User::new("carol", &[Role::Reviewer, Role::Admin]);
+
     let mut post = Post::new();
 
-    post.add_text("I ate a salad for lunch today");
+    post.add_text(&alice, "I ate a salad for lunch today").unwrap();
     assert_eq!("", post.content());
 
-    post.request_review();
+    // A draft can't be approved yet. The post stays a draft, and the error tells us why.
+    if let Err(err) = post.approve(&bob, None) {
+        println!("{} (the post is still a {})", err, post.state_name());
+    }
+
+    post.request_review(&alice).unwrap();
+    assert_eq!("", post.content());
+
+    // A reviewer can send the post back, and then the author can change it.
+    post.reject(&bob, Some("Say when you ate it")).unwrap();
+    post.add_text(&alice, " at noon").unwrap();
+    post.request_review(&alice).unwrap();
+
+    // It takes two approvals to publish a post.
+    post.approve(&bob, None).unwrap();
+    assert_eq!("", post.content());
+
+    post.approve(&carol, Some("Nice")).unwrap();
+    assert_eq!("I ate a salad for lunch today at noon", post.content());
+
+    // Only the author can change their post, and only an admin can take it down again.
+    if let Err(err) = post.unpublish(&bob, None) {
+        println!("{}", err);
+    }
+    post.unpublish(&carol, Some("Nobody wants to know what you ate")).unwrap();
     assert_eq!("", post.content());
 
-    post.approve();
-    assert_eq!("I ate a salad for lunch today", post.content());
+    // Everything that happened to the post, except for the refused actions.
+    for event in post.history() {
+        println!("{} did {:?}: {} -> {}", event.actor, event.action, event.from.as_str(), event.to.as_str());
+    }
 }
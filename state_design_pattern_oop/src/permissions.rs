@@ -0,0 +1,162 @@
+// Who may do what with a post.
+//
+// The workflow only knows whether an action is possible in a state. Whether a user may do it is
+// decided here, before the workflow is asked:
+//
+//   - Only authors write posts: they add text, and ask for a review. A draft can only be changed
+//     by its own author, who is whoever did the first action with the post.
+//   - Reviewers approve or reject posts, but not the posts they wrote themselves. A post needs
+//     the approvals of different reviewers, so nobody can approve it twice in the same review.
+//   - Admins can unpublish a post, which makes it a draft again.
+//
+// A user can have more than one role, e.g. someone who writes posts and also reviews other posts.
+
+use std::error::Error;
+use std::fmt;
+
+use crate::Action;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Author,
+    Reviewer,
+    Admin,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Author => "an author",
+            Role::Reviewer => "a reviewer",
+            Role::Admin => "an admin",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct User {
+    name: String,
+    roles: Vec<Role>,
+}
+
+impl User {
+    pub fn new(name: impl Into<String>, roles: &[Role]) -> User {
+        User { name: name.into(), roles: roles.to_vec() }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn has(&self, role: Role) -> bool {
+        self.roles.contains(&role)
+    }
+}
+
+/// Why a user may not do an action. The post did not change.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PermissionError {
+    /// The action needs a role that the user does not have.
+    MissingRole { user: String, action: Action, role: Role },
+    /// Only the author of a post may change it, or ask for a review.
+    NotTheAuthor { user: String, action: Action },
+    /// Reviewers can't approve or reject their own posts.
+    OwnPost { user: String, action: Action },
+    /// The reviewer already approved the post, and the next approval has to come from someone else.
+    AlreadyApproved { user: String },
+}
+
+impl fmt::Display for PermissionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PermissionError::MissingRole { user, action, role } => {
+                write!(f, "{} can't {} a post, because they are not {}", user, action, role.as_str())
+            }
+            PermissionError::NotTheAuthor { user, action } => {
+                write!(f, "{} can't {} this post, because they did not write it", user, action)
+            }
+            PermissionError::OwnPost { user, action } => {
+                write!(f, "{} can't {} this post, because they wrote it", user, action)
+            }
+            PermissionError::AlreadyApproved { user } => {
+                write!(f, "{} can't approve this post again, because they already approved it", user)
+            }
+        }
+    }
+}
+
+impl Error for PermissionError {}
+
+// Checks whether `user` may do `action` with a post written by `author`, and approved by `approvers`.
+// A new post has no author yet, so then any author may start it.
+pub(crate) fn check(user: &User, action: Action, author: Option<&str>, approvers: &[String]) -> Result<(), PermissionError> {
+    let role = match action {
+        Action::AddText | Action::RequestReview => Role::Author,
+        Action::Approve | Action::Reject => Role::Reviewer,
+        Action::Unpublish => Role::Admin,
+    };
+    if !user.has(role) {
+        return Err(PermissionError::MissingRole { user: user.name.clone(), action, role });
+    }
+
+    let wrote_it = author == Some(user.name());
+    match role {
+        Role::Author if author.is_some() && !wrote_it => {
+            Err(PermissionError::NotTheAuthor { user: user.name.clone(), action })
+        }
+        Role::Reviewer if wrote_it => Err(PermissionError::OwnPost { user: user.name.clone(), action }),
+        Role::Reviewer if action == Action::Approve && approvers.iter().any(|approver| approver == user.name()) => {
+            Err(PermissionError::AlreadyApproved { user: user.name.clone() })
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Post, PostError, StateName};
+
+    #[test]
+    fn authors_write_and_reviewers_review() {
+        let alice = User::new("alice", &[Role::Author, Role::Reviewer]);
+        let bob = User::new("bob", &[Role::Author, Role::Reviewer]);
+        let carol = User::new("carol", &[Role::Reviewer]);
+
+        let mut post = Post::new();
+        post.add_text(&alice, "I ate a salad").unwrap();
+        assert_eq!(Some("alice"), post.author());
+
+        let err = post.add_text(&bob, " and a steak").unwrap_err();
+        assert_eq!(PostError::Permission(PermissionError::NotTheAuthor { user: "bob".to_string(), action: Action::AddText }), err);
+        assert_eq!("carol can't add text to a post, because they are not an author", post.add_text(&carol, "!").unwrap_err().to_string());
+
+        post.request_review(&alice).unwrap();
+        assert_eq!("alice can't approve this post, because they wrote it", post.approve(&alice, None).unwrap_err().to_string());
+        post.approve(&bob, None).unwrap();
+        post.approve(&carol, None).unwrap();
+        assert_eq!("I ate a salad", post.content());
+    }
+
+    #[test]
+    fn only_admins_unpublish() {
+        let alice = User::new("alice", &[Role::Author]);
+        let bob = User::new("bob", &[Role::Reviewer]);
+        let carol = User::new("carol", &[Role::Reviewer]);
+        let dave = User::new("dave", &[Role::Admin]);
+
+        let mut post = Post::new();
+        post.request_review(&alice).unwrap();
+        post.approve(&bob, None).unwrap();
+        post.approve(&carol, None).unwrap();
+
+        assert!(matches!(post.unpublish(&bob, None), Err(PostError::Permission(PermissionError::MissingRole { role: Role::Admin, .. }))));
+        post.unpublish(&dave, Some("Off topic")).unwrap();
+        assert_eq!(StateName::Draft, post.state());
+        assert_eq!("", post.content());
+
+        // The post is a draft again, so only its author can change it.
+        assert!(post.add_text(&dave, "spam").is_err());
+        post.add_text(&alice, "I ate a soup").unwrap();
+    }
+}
@@ -0,0 +1,197 @@
+// Saving posts to disk, and loading them again.
+//
+// The app only talks to the PostRepository trait, so it does not need to know where the posts
+// are kept. JsonRepository keeps every post as a JSON file in one directory, named after the id
+// of the post (like "salad.json"), so the posts can also be read (or fixed) by hand.
+
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::{Post, StateName};
+
+pub trait PostRepository {
+    type Error;
+
+    /// Saves the post under `id`, and replaces whatever was saved under that id before.
+    fn save(&mut self, id: &str, post: &Post) -> Result<(), Self::Error>;
+
+    /// Returns None if there is no post with this id.
+    fn load(&self, id: &str) -> Result<Option<Post>, Self::Error>;
+
+    /// Every post, together with its id, sorted by id.
+    fn list(&self) -> Result<Vec<(String, Post)>, Self::Error>;
+
+    /// Only the posts in one state, e.g. to show a reviewer what is waiting for them.
+    fn list_by_state(&self, state: StateName) -> Result<Vec<(String, Post)>, Self::Error> {
+        let posts = self.list()?;
+        Ok(posts.into_iter().filter(|(_, post)| post.state() == state).collect())
+    }
+}
+
+#[derive(Debug)]
+pub enum RepositoryError {
+    Io(io::Error),
+    /// A file is not a valid post, for example because its history can't be replayed.
+    Json(serde_json::Error),
+    /// The id is used as a file name, so it can't be empty, contain a slash, or start with a dot.
+    InvalidId(String),
+}
+
+impl fmt::Display for RepositoryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RepositoryError::Io(err) => write!(f, "{}", err),
+            RepositoryError::Json(err) => write!(f, "invalid post: {}", err),
+            RepositoryError::InvalidId(id) => write!(f, "invalid post id '{}'", id),
+        }
+    }
+}
+
+impl Error for RepositoryError {}
+
+impl From<io::Error> for RepositoryError {
+    fn from(err: io::Error) -> RepositoryError {
+        RepositoryError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for RepositoryError {
+    fn from(err: serde_json::Error) -> RepositoryError {
+        RepositoryError::Json(err)
+    }
+}
+
+pub struct JsonRepository {
+    dir: PathBuf,
+}
+
+impl JsonRepository {
+    /// Uses `dir` for the posts, and creates it if it does not exist yet.
+    pub fn open(dir: impl Into<PathBuf>) -> io::Result<JsonRepository> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(JsonRepository { dir })
+    }
+
+    fn path(&self, id: &str) -> Result<PathBuf, RepositoryError> {
+        if id.is_empty() || id.starts_with('.') || id.contains(['/', '\\']) {
+            return Err(RepositoryError::InvalidId(id.to_string()));
+        }
+        Ok(self.dir.join(format!("{}.json", id)))
+    }
+}
+
+impl PostRepository for JsonRepository {
+    type Error = RepositoryError;
+
+    fn save(&mut self, id: &str, post: &Post) -> Result<(), RepositoryError> {
+        let path = self.path(id)?;
+
+        // We write to another file first, and then rename it, which replaces the old file at once.
+        // So if we crash while writing, the post that was saved before is still there.
+        let temporary = path.with_extension("json.tmp");
+        fs::write(&temporary, serde_json::to_vec_pretty(post)?)?;
+        fs::rename(&temporary, &path)?;
+        Ok(())
+    }
+
+    fn load(&self, id: &str) -> Result<Option<Post>, RepositoryError> {
+        match fs::read(self.path(id)?) {
+            Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn list(&self) -> Result<Vec<(String, Post)>, RepositoryError> {
+        let mut posts = Vec::new();
+
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if let Some(id) = post_id(&path) {
+                let post = serde_json::from_slice(&fs::read(&path)?)?;
+                posts.push((id.to_string(), post));
+            }
+        }
+
+        posts.sort_by(|(a, _), (b, _)| a.cmp(b));
+        Ok(posts)
+    }
+}
+
+// "salad.json" is the post "salad". Anything else in the directory is not a post.
+fn post_id(path: &Path) -> Option<&str> {
+    match path.extension()?.to_str()? {
+        "json" => path.file_stem()?.to_str(),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{alice, bob, carol};
+
+    #[test]
+    fn saves_and_loads_the_state() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut repository = JsonRepository::open(dir.path()).unwrap();
+
+        let mut post = Post::new();
+        post.add_text(&alice(), "I ate a salad for lunch today").unwrap();
+        post.request_review(&alice()).unwrap();
+        post.approve(&bob(), None).unwrap();
+        repository.save("salad", &post).unwrap();
+
+        // What is stored is the history of the post, and not its state.
+        let json = fs::read_to_string(dir.path().join("salad.json")).unwrap();
+        assert!(json.contains(r#""action": "Approve""#));
+        assert!(json.contains(r#""actor": "bob""#));
+        assert!(!json.contains(r#""state""#));
+
+        // And the loaded post behaves like the one we saved: one more approval publishes it.
+        let mut loaded = repository.load("salad").unwrap().unwrap();
+        assert_eq!(StateName::PendingReview, loaded.state());
+        loaded.approve(&carol(), None).unwrap();
+        assert_eq!("I ate a salad for lunch today", loaded.content());
+
+        assert!(repository.load("soup").unwrap().is_none());
+    }
+
+    #[test]
+    fn lists_posts_by_state() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut repository = JsonRepository::open(dir.path()).unwrap();
+
+        let mut published = Post::new();
+        published.request_review(&alice()).unwrap();
+        published.approve(&bob(), None).unwrap();
+        published.approve(&carol(), None).unwrap();
+        repository.save("b-published", &published).unwrap();
+        repository.save("a-draft", &Post::new()).unwrap();
+        repository.save("c-draft", &Post::new()).unwrap();
+
+        let drafts: Vec<String> = repository
+            .list_by_state(StateName::Draft)
+            .unwrap()
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect();
+        assert_eq!(vec!["a-draft", "c-draft"], drafts);
+        assert_eq!(3, repository.list().unwrap().len());
+    }
+
+    #[test]
+    fn rejects_bad_ids_and_broken_histories() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut repository = JsonRepository::open(dir.path()).unwrap();
+
+        assert!(matches!(repository.save("../escape", &Post::new()), Err(RepositoryError::InvalidId(_))));
+
+        fs::write(dir.path().join("odd.json"), r#"{"events":[{"action":"Approve","from":"Draft","to":"Published","actor":"mallory","timestamp":{"secs_since_epoch":0,"nanos_since_epoch":0}}]}"#).unwrap();
+        assert!(matches!(repository.load("odd"), Err(RepositoryError::Json(_))));
+    }
+}
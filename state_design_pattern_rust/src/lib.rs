@@ -4,14 +4,35 @@
 //
 // Also, because the content field of both Post and DraftPost are private,
 // the only way to create a DraftPost, is to call new() on Post.
+//
+// Who may do what is encoded in types as well: every action needs the token of its role
+// (see roles.rs). Because a post moves into the next state, an action that is denied gives
+// the post back together with the reason, so it is not lost.
+// The tokens keep honest code from forgetting a check, but they are not proof of anything:
+// Roles can be built and granted anywhere, see roles.rs.
+
+pub mod roles;
+
+use roles::{AdminToken, AuthorToken, ReviewerToken};
+pub use roles::{Denied, Role, Roles};
+
+// The result of an action that moves the post into another state: the post in its new state,
+// or the post as it was, if the user may not do this with it.
+pub type Outcome<Next, Current> = Result<Next, (Current, Denied)>;
 
+#[derive(Debug)]
 pub struct Post {
+    author: String,
     content: String,
 }
 
 impl Post {
-    pub fn new() -> DraftPost {
+    // new() does not return a Post on purpose: every post starts out as a draft,
+    // and only becomes a Post once it is published.
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(author: &AuthorToken) -> DraftPost {
         DraftPost {
+            author: author.name().to_string(),
             content: String::new(),
         }
     }
@@ -19,32 +40,182 @@ impl Post {
     pub fn content(&self) -> &str {
         &self.content
     }
+
+    pub fn author(&self) -> &str {
+        &self.author
+    }
+
+    // Only an admin can take a published post down. It becomes a draft again,
+    // which still belongs to its author.
+    pub fn unpublish(self, _admin: &AdminToken) -> DraftPost {
+        DraftPost {
+            author: self.author,
+            content: self.content,
+        }
+    }
 }
 
+// Only a DraftPost has add_text(), so text can only be added to a draft.
+// Calling it on a post that is waiting for review does not even compile.
+#[derive(Debug)]
 pub struct DraftPost {
+    author: String,
     content: String,
 }
 
 impl DraftPost {
-    pub fn add_text(&mut self, text: &str) {
-        self.content.push_str(text)
+    pub fn add_text(&mut self, author: &AuthorToken, text: &str) -> Result<(), Denied> {
+        author.check(&self.author)?;
+        self.content.push_str(text);
+        Ok(())
     }
 
-    pub fn request_review(self) -> PendingReviewPost {
-        PendingReviewPost {
-            content: self.content,
+    pub fn request_review(self, author: &AuthorToken) -> Outcome<PendingReviewPost, DraftPost> {
+        if let Err(denied) = author.check(&self.author) {
+            return Err((self, denied));
         }
+        Ok(PendingReviewPost {
+            author: self.author,
+            content: self.content,
+        })
     }
 }
 
+// A post needs two approvals to be published, from two different reviewers. How many it has
+// so far is part of its type too: the first approval turns a PendingReviewPost into a
+// PendingSecondReviewPost, and only the second approval turns it into a Post.
+#[derive(Debug)]
 pub struct PendingReviewPost {
+    author: String,
     content: String,
 }
 
 impl PendingReviewPost {
-    pub fn approve(self) -> Post {
-        Post {
+    pub fn approve(self, reviewer: &ReviewerToken) -> Outcome<PendingSecondReviewPost, PendingReviewPost> {
+        if let Err(denied) = reviewer.check(&self.author) {
+            return Err((self, denied));
+        }
+        Ok(PendingSecondReviewPost {
+            author: self.author,
+            content: self.content,
+            first_reviewer: reviewer.name().to_string(),
+        })
+    }
+
+    // The reviewer sends the post back to the author, who can then change it.
+    pub fn reject(self, reviewer: &ReviewerToken) -> Outcome<DraftPost, PendingReviewPost> {
+        if let Err(denied) = reviewer.check(&self.author) {
+            return Err((self, denied));
+        }
+        Ok(DraftPost {
+            author: self.author,
             content: self.content,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct PendingSecondReviewPost {
+    author: String,
+    content: String,
+    // Who gave the first approval, so they can't give the second one as well.
+    first_reviewer: String,
+}
+
+impl PendingSecondReviewPost {
+    pub fn approve(self, reviewer: &ReviewerToken) -> Outcome<Post, PendingSecondReviewPost> {
+        if let Err(denied) = reviewer.check_second(&self.author, &self.first_reviewer) {
+            return Err((self, denied));
         }
+        Ok(Post {
+            author: self.author,
+            content: self.content,
+        })
+    }
+
+    // The first approval is lost: after the changes, the post needs two approvals again.
+    pub fn reject(self, reviewer: &ReviewerToken) -> Outcome<DraftPost, PendingSecondReviewPost> {
+        if let Err(denied) = reviewer.check(&self.author) {
+            return Err((self, denied));
+        }
+        Ok(DraftPost {
+            author: self.author,
+            content: self.content,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // alice writes posts, bob and carol review them, and dave is an admin.
+    fn roles() -> Roles {
+        let mut roles = Roles::new();
+        roles.grant("alice", Role::Author);
+        roles.grant("bob", Role::Reviewer);
+        roles.grant("carol", Role::Reviewer);
+        roles.grant("dave", Role::Admin);
+        roles
+    }
+
+    #[test]
+    fn needs_two_approvals() {
+        let roles = roles();
+        let alice = roles.author("alice").unwrap();
+        let (bob, carol) = (roles.reviewer("bob").unwrap(), roles.reviewer("carol").unwrap());
+
+        let mut post = Post::new(&alice);
+        post.add_text(&alice, "I ate a salad for lunch today").unwrap();
+
+        // The second approval has to come from another reviewer.
+        let post = post.request_review(&alice).unwrap().approve(&bob).unwrap();
+        let (post, denied) = post.approve(&bob).unwrap_err();
+        assert_eq!(Denied::AlreadyApproved, denied);
+
+        let post = post.approve(&carol).unwrap();
+        assert_eq!("I ate a salad for lunch today", post.content());
+    }
+
+    #[test]
+    fn reject_goes_back_to_draft() {
+        let roles = roles();
+        let alice = roles.author("alice").unwrap();
+        let (bob, carol) = (roles.reviewer("bob").unwrap(), roles.reviewer("carol").unwrap());
+
+        let mut post = Post::new(&alice);
+        post.add_text(&alice, "I ate a salad").unwrap();
+
+        // Rejected after the first approval, so the author can add text again.
+        let mut post = post.request_review(&alice).unwrap().approve(&bob).unwrap().reject(&bob).unwrap();
+        post.add_text(&alice, " for lunch").unwrap();
+
+        let post = post.request_review(&alice).unwrap().reject(&bob).unwrap().request_review(&alice).unwrap();
+        assert_eq!("I ate a salad for lunch", post.approve(&bob).unwrap().approve(&carol).unwrap().content());
+    }
+
+    #[test]
+    fn authors_and_reviewers_stay_apart() {
+        let mut roles = roles();
+        roles.grant("bob", Role::Author);
+        roles.grant("alice", Role::Reviewer);
+        let (alice, bob) = (roles.author("alice").unwrap(), roles.author("bob").unwrap());
+
+        let mut post = Post::new(&alice);
+        assert_eq!(Err(Denied::NotTheAuthor), post.add_text(&bob, "I ate a steak"));
+        post.add_text(&alice, "I ate a salad").unwrap();
+
+        // Being a reviewer is not enough to approve your own post. The post is given back.
+        let post = post.request_review(&alice).unwrap();
+        let (post, denied) = post.approve(&roles.reviewer("alice").unwrap()).unwrap_err();
+        assert_eq!(Denied::OwnPost, denied);
+
+        let (bob, carol) = (roles.reviewer("bob").unwrap(), roles.reviewer("carol").unwrap());
+        let post = post.approve(&bob).unwrap().approve(&carol).unwrap();
+
+        // Only an admin can unpublish, and then the post is alice's draft again.
+        let mut post = post.unpublish(&roles.admin("dave").unwrap());
+        post.add_text(&alice, " for lunch").unwrap();
+        assert_eq!("alice", post.request_review(&alice).unwrap().approve(&carol).unwrap().approve(&bob).unwrap().author());
     }
 }
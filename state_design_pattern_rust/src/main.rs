@@ -9,15 +9,43 @@ Another downside is duplication.
 
 So by implementing the State pattern exactly as in OOP, we are not taking full advantage of Rust.
  */
-use state_design_pattern_rust::Post;
+use state_design_pattern_rust::{Post, Role, Roles};
 
 fn main() {
-    let mut post = Post::new();
+    let mut roles = Roles::new();
+    roles.grant("alice", Role::Author);
+    roles.grant("bob", Role::Reviewer);
+    roles.grant("carol", Role::Reviewer);
+    roles.grant("dave", Role::Admin);
 
-    post.add_text("I ate a salad for lunch today");
+    // Every action needs the token of its role. Without a ReviewerToken, there is no way to call
+    // approve(), so forgetting to check whether someone is a reviewer does not compile.
+    let alice = roles.author("alice").unwrap();
+    let bob = roles.reviewer("bob").unwrap();
+    let carol = roles.reviewer("carol").unwrap();
+    assert!(roles.reviewer("alice").is_none());
 
-    let post = post.request_review();
+    let mut post = Post::new(&alice);
 
-    let post = post.approve();
-    assert_eq!("I ate a salad for lunch today", post.content());
+    post.add_text(&alice, "I ate a salad for lunch today").unwrap();
+
+    // A reviewer can send the post back, and then the author can change it.
+    let mut post = post.request_review(&alice).unwrap().reject(&bob).unwrap();
+    post.add_text(&alice, " at noon").unwrap();
+
+    let post = post.request_review(&alice).unwrap();
+
+    // It takes two approvals to publish a post.
+    let post = post.approve(&bob).unwrap();
+    let post = post.approve(&carol).unwrap();
+    assert_eq!("I ate a salad for lunch today at noon", post.content());
+
+    // An admin can take the post down again, and then it is a draft of its author.
+    // Which post someone wrote is only known at runtime, so that is checked when the action is done.
+    roles.grant("erin", Role::Author);
+    let mut post = post.unpublish(&roles.admin("dave").unwrap());
+    if let Err(denied) = post.add_text(&roles.author("erin").unwrap(), " Boring!") {
+        println!("erin can't change the post: {}", denied);
+    }
+    post.add_text(&alice, " It was a Caesar salad.").unwrap();
 }
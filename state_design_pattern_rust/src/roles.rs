@@ -0,0 +1,168 @@
+// Who may do what with a post, encoded in types as far as we can.
+//
+// Every action needs a token for the role it belongs to: writing a draft needs an AuthorToken,
+// approving or rejecting needs a ReviewerToken, and unpublishing needs an AdminToken. The fields
+// of the tokens are private, so the only way to get one is to ask Roles for it, which only hands
+// it out to users who have that role. After that, the compiler makes sure that e.g. approve() is
+// never called without a reviewer: there is simply no way to call it without the token.
+//
+// Which post a user wrote is only known at runtime, so the last rules are still checked then:
+// only the author of a post may change it, reviewers can't review their own posts, and the two
+// approvals of a post have to come from two different reviewers.
+//
+// The tokens are a convenience, not a proof. Roles::new() and grant() are public, so any code
+// can build a Roles, grant itself every role, and get every token. What the tokens do is make it
+// impossible to forget a check: code that only gets its tokens from the Roles of the app can't
+// approve a post for someone who is not a reviewer. Deciding who gets which role, e.g. after
+// checking a login, is up to the code that builds the Roles.
+
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Author,
+    Reviewer,
+    Admin,
+}
+
+/// Knows the roles of every user, and hands out the tokens for them.
+///
+/// Anyone can build one, so a token only shows that *some* Roles gave it out. The app should
+/// build its Roles once, from the roles it trusts, and only get tokens from that one.
+#[derive(Default)]
+pub struct Roles {
+    users: Vec<(String, Role)>,
+}
+
+impl Roles {
+    pub fn new() -> Roles {
+        Roles { users: Vec::new() }
+    }
+
+    /// Gives `name` another role. A user can have more than one.
+    /// Nothing checks whether the caller may hand out this role.
+    pub fn grant(&mut self, name: &str, role: Role) {
+        self.users.push((name.to_string(), role));
+    }
+
+    fn has(&self, name: &str, role: Role) -> bool {
+        self.users.iter().any(|(user, has)| user == name && *has == role)
+    }
+
+    /// Returns None if `name` is not an author.
+    pub fn author(&self, name: &str) -> Option<AuthorToken> {
+        self.has(name, Role::Author).then(|| AuthorToken { name: name.to_string() })
+    }
+
+    pub fn reviewer(&self, name: &str) -> Option<ReviewerToken> {
+        self.has(name, Role::Reviewer).then(|| ReviewerToken { name: name.to_string() })
+    }
+
+    pub fn admin(&self, name: &str) -> Option<AdminToken> {
+        self.has(name, Role::Admin).then(|| AdminToken { name: name.to_string() })
+    }
+}
+
+/// Given out by Roles to users who are authors.
+#[derive(Debug)]
+pub struct AuthorToken {
+    name: String,
+}
+
+impl AuthorToken {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    // Only the author of a post may change it.
+    pub(crate) fn check(&self, author: &str) -> Result<(), Denied> {
+        match self.name == author {
+            true => Ok(()),
+            false => Err(Denied::NotTheAuthor),
+        }
+    }
+}
+
+/// Given out by Roles to users who are reviewers.
+#[derive(Debug)]
+pub struct ReviewerToken {
+    name: String,
+}
+
+impl ReviewerToken {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    // Reviewers can review every post, except for their own.
+    pub(crate) fn check(&self, author: &str) -> Result<(), Denied> {
+        match self.name == author {
+            true => Err(Denied::OwnPost),
+            false => Ok(()),
+        }
+    }
+
+    // The second approval has to come from someone else than the first one.
+    pub(crate) fn check_second(&self, author: &str, first_reviewer: &str) -> Result<(), Denied> {
+        self.check(author)?;
+        match self.name == first_reviewer {
+            true => Err(Denied::AlreadyApproved),
+            false => Ok(()),
+        }
+    }
+}
+
+/// Given out by Roles to users who are admins.
+#[derive(Debug)]
+pub struct AdminToken {
+    name: String,
+}
+
+impl AdminToken {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// Why a user with the right role may still not do something with a post.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Denied {
+    /// Someone else wrote the post.
+    NotTheAuthor,
+    /// Reviewers can't approve or reject their own posts.
+    OwnPost,
+    /// The reviewer gave the first approval, and the second one has to come from someone else.
+    AlreadyApproved,
+}
+
+impl fmt::Display for Denied {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Denied::NotTheAuthor => write!(f, "only the author can change a draft"),
+            Denied::OwnPost => write!(f, "reviewers can't review their own posts"),
+            Denied::AlreadyApproved => write!(f, "the second approval has to come from another reviewer"),
+        }
+    }
+}
+
+impl Error for Denied {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_only_for_granted_roles() {
+        let mut roles = Roles::new();
+        roles.grant("alice", Role::Author);
+        roles.grant("alice", Role::Reviewer);
+        roles.grant("dave", Role::Admin);
+
+        assert_eq!("alice", roles.author("alice").unwrap().name());
+        assert!(roles.reviewer("alice").is_some());
+        assert!(roles.admin("alice").is_none());
+        assert!(roles.author("dave").is_none());
+        assert!(roles.reviewer("mallory").is_none());
+    }
+}
@@ -0,0 +1,160 @@
+// Drawing a machine as a diagram, so we can see the whole workflow at once.
+//
+// Both formats are made from the rules of the machine, so the diagram can't get out of date.
+// DOT is the language of Graphviz (`dot -Tsvg post.dot > post.svg`), and Mermaid diagrams are
+// drawn by GitHub and many other Markdown viewers, when put in a ```mermaid block.
+//
+// States and events are written with their Debug names (like PendingReview), and a rule with a
+// guard gets its name in brackets after the event, like "Approve [last approval]".
+//
+// In DOT, every name is quoted, because a Debug name like Dim(3) or "Front door" is not a valid
+// ID on its own. The point where the machine starts is the node "__start", so that it does not
+// clash with a state that is called Start.
+//
+// Mermaid has no quoted IDs, so every state gets a short ID (s0, s1, ...) in the order of
+// states(), and its name is given as a quoted description: `state "Dim(3)" as s1`. The names
+// and labels can't have quotes in them, so those are written as Mermaid entities, like #quot;.
+
+use std::fmt::{Debug, Write};
+
+use crate::{Machine, Rule};
+
+impl<S, E, C> Machine<S, E, C>
+where
+    S: Copy + PartialEq + Debug,
+    E: Copy + PartialEq + Debug,
+{
+    /// The machine as a Graphviz digraph called `name`.
+    pub fn to_dot(&self, name: &str) -> String {
+        let mut dot = String::new();
+        // Writing to a String can't fail, so we ignore the results of writeln!().
+        let _ = writeln!(dot, "digraph {} {{", quote(name));
+        let _ = writeln!(dot, "    {} [shape=point];", quote(START));
+        let _ = writeln!(dot, "    {} -> {};", quote(START), id(self.initial()));
+
+        // States that can't be left are drawn with a double circle.
+        for &state in self.states() {
+            let shape = if self.is_final(state) { "doublecircle" } else { "circle" };
+            let _ = writeln!(dot, "    {} [shape={}];", id(state), shape);
+        }
+        for rule in self.rules() {
+            let _ = writeln!(dot, "    {} -> {} [label={}];", id(rule.from), id(rule.to), quote(&label(rule)));
+        }
+
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// The machine as a Mermaid state diagram.
+    pub fn to_mermaid(&self) -> String {
+        let mut mermaid = String::from("stateDiagram-v2\n");
+        for (index, state) in self.states().iter().enumerate() {
+            let _ = writeln!(mermaid, "    state \"{}\" as s{}", escape(&format!("{:?}", state)), index);
+        }
+        let _ = writeln!(mermaid, "    [*] --> {}", self.alias(self.initial()));
+
+        for rule in self.rules() {
+            let _ = writeln!(mermaid, "    {} --> {}: {}", self.alias(rule.from), self.alias(rule.to), escape(&label(rule)));
+        }
+        for &state in self.states().iter().filter(|&&state| self.is_final(state)) {
+            let _ = writeln!(mermaid, "    {} --> [*]", self.alias(state));
+        }
+
+        mermaid
+    }
+
+    // The Mermaid ID of a state, from its place in states().
+    fn alias(&self, state: S) -> String {
+        let index = self.states().iter().position(|&known| known == state).unwrap_or_default();
+        format!("s{}", index)
+    }
+
+    fn is_final(&self, state: S) -> bool {
+        !self.rules().iter().any(|rule| rule.from == state)
+    }
+}
+
+// The name of the node where the machine starts. States are never called this.
+const START: &str = "__start";
+
+fn id(state: impl Debug) -> String {
+    quote(&format!("{:?}", state))
+}
+
+// A DOT string in quotes, where the quotes and backslashes in `text` are escaped.
+fn quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// Mermaid text, with the characters that would end a name or a label written as entities.
+// One character at a time, so the # and ; of an entity are not escaped again.
+fn escape(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '#' => "#35;".to_string(),
+            '"' => "#quot;".to_string(),
+            ';' => "#59;".to_string(),
+            _ => c.to_string(),
+        })
+        .collect()
+}
+
+fn label<S, E: Debug, C>(rule: &Rule<S, E, C>) -> String {
+    match rule.guard() {
+        Some(guard) => format!("{:?} [{}]", rule.event, guard),
+        None => format!("{:?}", rule.event),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Machine;
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    enum Light {
+        Start,
+        Dim(u8),
+    }
+
+    // The Debug name of a &str event has quotes in it, which have to be escaped in the label.
+    #[test]
+    fn dot_quotes_every_id() {
+        let machine = Machine::<Light, &str, ()>::builder(Light::Start)
+            .state(Light::Dim(3))
+            .rule(Light::Start, "turn up", Light::Dim(3))
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            r#"digraph "Living room" {
+    "__start" [shape=point];
+    "__start" -> "Start";
+    "Start" [shape=circle];
+    "Dim(3)" [shape=doublecircle];
+    "Start" -> "Dim(3)" [label="\"turn up\""];
+}
+"#,
+            machine.to_dot("Living room")
+        );
+    }
+
+    #[test]
+    fn mermaid_names_every_state() {
+        let machine = Machine::<Light, &str, ()>::builder(Light::Start)
+            .state(Light::Dim(3))
+            .rule(Light::Start, "turn up", Light::Dim(3))
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            r#"stateDiagram-v2
+    state "Start" as s0
+    state "Dim(3)" as s1
+    [*] --> s0
+    s0 --> s1: #quot;turn up#quot;
+    s1 --> [*]
+"#,
+            machine.to_mermaid()
+        );
+    }
+}
@@ -0,0 +1,257 @@
+// A state machine that is not tied to blog posts, so every workflow of the app can use it.
+// The blog Post of state_design_pattern_oop is built on it, and is the example to look at.
+//
+// With the State trait, every state was a struct, and every action was a method on the trait.
+// So a new workflow needed a new trait, and a new action meant changing every state. Here, the
+// states and events are just values (usually enums), and the rules between them are data:
+// "in state `from`, event `event` leads to state `to`". They are declared once with a
+// MachineBuilder, which also checks that every state can be reached from the first one.
+//
+// A rule can have a guard, which decides whether it applies, and an effect, which changes some
+// data that belongs to the workflow (the "context", like how many approvals a post has). If several
+// rules match a state and event, the first one whose guard passes is used.
+
+use std::error::Error;
+use std::fmt::{self, Debug};
+
+pub mod graph;
+
+/// One rule of a machine: `event` in state `from` leads to state `to`.
+pub struct Rule<S, E, C> {
+    pub from: S,
+    pub event: E,
+    pub to: S,
+    guard: Option<Guard<C>>,
+    effect: Option<fn(&mut C)>,
+}
+
+// A guard has a name, so we can tell what it checks without calling it.
+struct Guard<C> {
+    name: &'static str,
+    check: fn(&C) -> bool,
+}
+
+impl<S, E, C> Rule<S, E, C> {
+    /// What the guard of the rule checks, if it has one.
+    pub fn guard(&self) -> Option<&'static str> {
+        self.guard.as_ref().map(|guard| guard.name)
+    }
+
+    fn applies(&self, context: &C) -> bool {
+        self.guard.as_ref().is_none_or(|guard| (guard.check)(context))
+    }
+}
+
+pub struct Machine<S, E, C> {
+    initial: S,
+    states: Vec<S>,
+    rules: Vec<Rule<S, E, C>>,
+}
+
+impl<S, E, C> Machine<S, E, C>
+where
+    S: Copy + PartialEq + Debug,
+    E: Copy + PartialEq,
+{
+    /// Starts a machine that begins in `initial`.
+    pub fn builder(initial: S) -> MachineBuilder<S, E, C> {
+        MachineBuilder::new(initial)
+    }
+
+    pub fn initial(&self) -> S {
+        self.initial
+    }
+
+    /// Every state, in the order they were declared.
+    pub fn states(&self) -> &[S] {
+        &self.states
+    }
+
+    pub fn rules(&self) -> &[Rule<S, E, C>] {
+        &self.rules
+    }
+
+    /// Returns the state that `event` leads to, and runs the effect of the rule on `context`.
+    /// Returns None if the event is not allowed in `state`, and then `context` is not changed.
+    pub fn fire(&self, state: S, event: E, context: &mut C) -> Option<S> {
+        let rule = self
+            .rules
+            .iter()
+            .find(|rule| rule.from == state && rule.event == event && rule.applies(context))?;
+
+        if let Some(effect) = rule.effect {
+            effect(context);
+        }
+        Some(rule.to)
+    }
+
+    /// Whether `event` is allowed in `state`, without changing anything.
+    pub fn allows(&self, state: S, event: E, context: &C) -> bool {
+        self.rules
+            .iter()
+            .any(|rule| rule.from == state && rule.event == event && rule.applies(context))
+    }
+}
+
+pub struct MachineBuilder<S, E, C> {
+    initial: S,
+    states: Vec<S>,
+    rules: Vec<Rule<S, E, C>>,
+}
+
+impl<S, E, C> MachineBuilder<S, E, C>
+where
+    S: Copy + PartialEq + Debug,
+    E: Copy + PartialEq,
+{
+    pub fn new(initial: S) -> MachineBuilder<S, E, C> {
+        MachineBuilder { initial, states: vec![initial], rules: Vec::new() }
+    }
+
+    /// Declares another state. Only declared states can be used in rules.
+    pub fn state(mut self, state: S) -> MachineBuilder<S, E, C> {
+        if !self.states.contains(&state) {
+            self.states.push(state);
+        }
+        self
+    }
+
+    /// Adds a rule. Use guard() and effect() right after it to give it a guard or an effect.
+    pub fn rule(mut self, from: S, event: E, to: S) -> MachineBuilder<S, E, C> {
+        self.rules.push(Rule { from, event, to, guard: None, effect: None });
+        self
+    }
+
+    /// The last rule only applies if `check` returns true.
+    pub fn guard(mut self, name: &'static str, check: fn(&C) -> bool) -> MachineBuilder<S, E, C> {
+        self.last_rule().guard = Some(Guard { name, check });
+        self
+    }
+
+    /// The last rule runs `effect` when it is used.
+    pub fn effect(mut self, effect: fn(&mut C)) -> MachineBuilder<S, E, C> {
+        self.last_rule().effect = Some(effect);
+        self
+    }
+
+    // guard() and effect() without a rule before them are a mistake in the code, and not
+    // something that can go wrong at runtime, so we panic.
+    fn last_rule(&mut self) -> &mut Rule<S, E, C> {
+        self.rules.last_mut().expect("guard() and effect() need a rule() before them")
+    }
+
+    pub fn build(self) -> Result<Machine<S, E, C>, BuildError<S>> {
+        for rule in &self.rules {
+            for state in [rule.from, rule.to] {
+                if !self.states.contains(&state) {
+                    return Err(BuildError::UndeclaredState(state));
+                }
+            }
+        }
+
+        // We follow the rules from the first state, and see where we can get. We ignore the
+        // guards here, because whether they pass depends on the context.
+        let mut reached = vec![self.initial];
+        let mut index = 0;
+        while index < reached.len() {
+            let from = reached[index];
+            for rule in self.rules.iter().filter(|rule| rule.from == from) {
+                if !reached.contains(&rule.to) {
+                    reached.push(rule.to);
+                }
+            }
+            index += 1;
+        }
+
+        if let Some(&state) = self.states.iter().find(|state| !reached.contains(state)) {
+            return Err(BuildError::UnreachableState(state));
+        }
+
+        Ok(Machine { initial: self.initial, states: self.states, rules: self.rules })
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum BuildError<S> {
+    /// A rule uses a state that was not declared with state().
+    UndeclaredState(S),
+    /// No rules lead from the first state to this one.
+    UnreachableState(S),
+}
+
+impl<S: Debug> fmt::Display for BuildError<S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BuildError::UndeclaredState(state) => write!(f, "state {:?} is used in a rule, but was not declared", state),
+            BuildError::UnreachableState(state) => write!(f, "state {:?} can't be reached from the first state", state),
+        }
+    }
+}
+
+impl<S: Debug> Error for BuildError<S> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    enum Light {
+        Off,
+        On,
+        Broken,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    enum Switch {
+        Flip,
+    }
+
+    // The context counts how often the light was switched on. The bulb breaks the third time.
+    fn lamp() -> MachineBuilder<Light, Switch, u32> {
+        Machine::builder(Light::Off)
+            .state(Light::On)
+            .state(Light::Broken)
+            .rule(Light::Off, Switch::Flip, Light::Broken)
+            .guard("worn out", |uses| *uses >= 2)
+            .rule(Light::Off, Switch::Flip, Light::On)
+            .effect(|uses| *uses += 1)
+            .rule(Light::On, Switch::Flip, Light::Off)
+    }
+
+    #[test]
+    fn first_rule_with_a_passing_guard_wins() {
+        let machine = lamp().build().unwrap();
+        let mut uses = 0;
+        let mut light = machine.initial();
+
+        let mut seen = Vec::new();
+        for _ in 0..5 {
+            light = machine.fire(light, Switch::Flip, &mut uses).unwrap();
+            seen.push(light);
+        }
+        assert_eq!(vec![Light::On, Light::Off, Light::On, Light::Off, Light::Broken], seen);
+
+        // Nothing leaves Broken, and a refused event does not change the context.
+        assert_eq!(None, machine.fire(Light::Broken, Switch::Flip, &mut uses));
+        assert!(!machine.allows(Light::Broken, Switch::Flip, &uses));
+        assert_eq!(2, uses);
+        assert_eq!(Some("worn out"), machine.rules()[0].guard());
+    }
+
+    #[test]
+    fn finds_unreachable_and_undeclared_states() {
+        let unreachable = Machine::<Light, Switch, ()>::builder(Light::Off)
+            .state(Light::On)
+            .state(Light::Broken)
+            .rule(Light::Off, Switch::Flip, Light::On)
+            .rule(Light::On, Switch::Flip, Light::Off)
+            .build();
+        assert_eq!(Some(BuildError::UnreachableState(Light::Broken)), unreachable.err());
+
+        let undeclared = Machine::<Light, Switch, ()>::builder(Light::Off)
+            .rule(Light::Off, Switch::Flip, Light::On)
+            .build();
+        let err = undeclared.err().unwrap();
+        assert_eq!("state On is used in a rule, but was not declared", err.to_string());
+    }
+}
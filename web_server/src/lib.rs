@@ -1,6 +1,8 @@
-use std::thread::{self, JoinHandle};
+use std::thread;
 use std::sync::{mpsc, Arc, Mutex};
 
+pub mod range;
+
 struct Worker {
     id: usize,
     thread: Option<thread::JoinHandle<()>>,
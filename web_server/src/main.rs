@@ -1,8 +1,9 @@
 use std::{
-    io::{prelude::*, BufReader},
-    net::{TcpListener, TcpStream}, fs, time::Duration, thread,
+    io::{self, prelude::*, BufReader},
+    net::TcpListener, fs::File, path::{Component, Path}, time::Duration, thread,
 };
 use web_server::ThreadPool;
+use web_server::range::{self, RangeRequest};
 
 fn main() {
     let listener = TcpListener::bind("127.0.0.1:7878").unwrap();
@@ -19,31 +20,232 @@ fn main() {
     println!("Shutting down.");
 }
 
-fn handle_connection(mut stream: TcpStream) {
-    let mut buffer = [0; 1024];
-    stream.read(&mut buffer).unwrap();
+// The request line and the headers together may not be longer than this, so a client can't make
+// a worker read (and keep in memory) more and more bytes, by never ending a line.
+const MAX_HEADER_BYTES: u64 = 8 * 1024;
 
-    let get = b"GET / HTTP/1.1\r\n";
-    let sleep = b"GET /sleep HTTP/1.1\r\n";
+// Why we could not read a request.
+enum BadRequest {
+    // The request line and headers are longer than MAX_HEADER_BYTES.
+    TooLarge,
+    // Not UTF-8, or the client hung up before the headers were complete.
+    Malformed,
+}
+
+impl From<io::Error> for BadRequest {
+    fn from(_: io::Error) -> BadRequest {
+        BadRequest::Malformed
+    }
+}
+
+// Generic over the stream, so the tests can use a fake connection instead of a TcpStream.
+fn handle_connection<S: Read + Write>(mut stream: S) {
+    // A client can send anything, e.g. bytes that are not UTF-8, or hang up before the headers
+    // are complete. That is the client's fault, so we answer 400 (or 431 if the headers are too
+    // long) and drop the connection, instead of panicking in the worker.
+    let (request_line, range_header) = match read_request(&mut stream) {
+        Ok(request) => request,
+        Err(BadRequest::TooLarge) => {
+            let _ = stream.write_all(b"HTTP/1.1 431 REQUEST HEADER FIELDS TOO LARGE\r\nContent-Length: 0\r\n\r\n");
+            return;
+        }
+        Err(BadRequest::Malformed) => {
+            let _ = stream.write_all(b"HTTP/1.1 400 BAD REQUEST\r\nContent-Length: 0\r\n\r\n");
+            return;
+        }
+    };
+
+    let (status_line, filename) = match request_line.trim_end() {
+        "GET / HTTP/1.1" => ("HTTP/1.1 200 OK", "hello.html".to_string()),
+        "GET /sleep HTTP/1.1" => {
+            thread::sleep(Duration::from_secs(5));
+            ("HTTP/1.1 200 OK", "hello.html".to_string())
+        }
+        // Large static files are served from the 'files' folder, e.g. GET /files/video.mp4
+        line => match static_file(line) {
+            Some(filename) => ("HTTP/1.1 200 OK", filename),
+            None => ("HTTP/1.1 404 NOT FOUND", "404.html".to_string()),
+        },
+    };
+
+    // We no longer read the whole file into a String. Instead we open it,
+    // and stream it (or the requested parts of it) into the TcpStream.
+    let (status_line, filename, opened) = match open_file(&filename) {
+        Ok(opened) => (status_line, filename, Ok(opened)),
+        Err(_) => ("HTTP/1.1 404 NOT FOUND", "404.html".to_string(), open_file("404.html")),
+    };
 
-    let (status_line, filename) = if buffer.starts_with(get) {
-        ("HTTP/1.1 200 OK", "hello.html")
-    } else if buffer.starts_with(sleep) {
-        thread::sleep(Duration::from_secs(5));
-        ("HTTP/1.1 200 OK", "hello.html")
-    } else {
-        ("HTTP/1.1 404 NOT FOUND", "404.html")
+    // If even 404.html is missing, or we can't tell how big the file is, that is our fault.
+    let (mut file, len) = match opened {
+        Ok(opened) => opened,
+        Err(e) => {
+            eprintln!("Failed to open {}: {}", filename, e);
+            let _ = stream.write_all(b"HTTP/1.1 500 INTERNAL SERVER ERROR\r\nContent-Length: 0\r\n\r\n");
+            return;
+        }
     };
+    let content_type = content_type(&filename);
+
+    // Ranges only make sense when we actually found the file.
+    let range_header = if status_line == "HTTP/1.1 200 OK" { range_header.as_deref() } else { None };
+
+    let result = match range::parse_range(range_header, len) {
+        RangeRequest::Full => range::write_full(&mut stream, status_line, content_type, &mut file, len),
+        RangeRequest::Partial(ranges) => range::write_partial(&mut stream, content_type, &mut file, len, &ranges),
+        RangeRequest::Unsatisfiable => range::write_unsatisfiable(&mut stream, len),
+    };
+
+    // The client might have hung up in the middle of a download, that should not bring down a worker.
+    if let Err(e) = result {
+        eprintln!("Failed to send {}: {}", filename, e);
+    }
+}
+
+// Opens a file, and tells how big it is. A directory opens just fine on Linux, but can't be
+// read, so it counts as not found, just like a file that does not exist.
+fn open_file(filename: &str) -> io::Result<(File, u64)> {
+    let file = File::open(filename)?;
+    let metadata = file.metadata()?;
+    if !metadata.is_file() {
+        return Err(io::Error::new(io::ErrorKind::NotFound, "not a file"));
+    }
+    Ok((file, metadata.len()))
+}
+
+// Reads the request line, and the Range header if there is one, until the empty line that ends
+// the headers. Fails if the request is not UTF-8, ends before the headers do, or is too long.
+fn read_request<S: Read>(stream: &mut S) -> Result<(String, Option<String>), BadRequest> {
+    // We never read more than MAX_HEADER_BYTES, no matter how the lines are split up.
+    let mut reader = BufReader::new(stream).take(MAX_HEADER_BYTES);
+    let request_line = read_line(&mut reader)?;
+
+    let mut range_header = None;
+    loop {
+        let line = read_line(&mut reader)?;
+        if line.trim_end().is_empty() {
+            break;
+        }
+
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("range") {
+                range_header = Some(value.trim().to_string());
+            }
+        }
+    }
+
+    Ok((request_line, range_header))
+}
+
+// Reads one whole line. A line without a line ending means that the request ended early,
+// or that we reached the limit.
+fn read_line(reader: &mut io::Take<impl BufRead>) -> Result<String, BadRequest> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    match (line.ends_with('\n'), reader.limit()) {
+        (true, _) => Ok(line),
+        (false, 0) => Err(BadRequest::TooLarge),
+        (false, _) => Err(BadRequest::Malformed),
+    }
+}
+
+// Maps "GET /files/<name> HTTP/1.1" to "files/<name>".
+// Only plain file names are allowed, so a request like /files/../src/main.rs can not escape the folder.
+fn static_file(request_line: &str) -> Option<String> {
+    let path = request_line
+        .strip_prefix("GET /files/")?
+        .strip_suffix(" HTTP/1.1")?;
+
+    let only_normal_parts = Path::new(path)
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)));
+
+    if path.is_empty() || !only_normal_parts {
+        return None;
+    }
+
+    Some(format!("files/{}", path))
+}
 
-    let contents = fs::read_to_string(filename).unwrap();
+fn content_type(filename: &str) -> &'static str {
+    match Path::new(filename).extension().and_then(|ext| ext.to_str()) {
+        Some("html") => "text/html; charset=utf-8",
+        Some("txt") => "text/plain; charset=utf-8",
+        Some("json") => "application/json",
+        Some("png") => "image/png",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("mp4") => "video/mp4",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn static_files_stay_in_their_folder() {
+        assert_eq!(Some("files/video.mp4".to_string()), static_file("GET /files/video.mp4 HTTP/1.1"));
+        assert_eq!(Some("files/a/b.txt".to_string()), static_file("GET /files/a/b.txt HTTP/1.1"));
 
-    let response = format!(
-        "{}\r\nContent-Length: {}\r\n\r\n{}",
-        status_line,
-        contents.len(),
-        contents
-    );
+        assert_eq!(None, static_file("GET /files/../src/main.rs HTTP/1.1"));
+        assert_eq!(None, static_file("GET /files/a/../../Cargo.toml HTTP/1.1"));
+        assert_eq!(None, static_file("GET /files//etc/passwd HTTP/1.1"));
+        assert_eq!(None, static_file("GET /files/./hello.html HTTP/1.1"));
+        assert_eq!(None, static_file("GET /files/ HTTP/1.1"));
+        assert_eq!(None, static_file("POST /files/video.mp4 HTTP/1.1"));
+    }
+
+    // A connection that reads from `request`, and keeps what the server writes.
+    struct FakeStream {
+        request: io::Cursor<Vec<u8>>,
+        response: Vec<u8>,
+    }
 
-    stream.write_all(response.as_bytes()).unwrap();
-    stream.flush().unwrap();
+    impl Read for FakeStream {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.request.read(buf)
+        }
+    }
+
+    impl Write for FakeStream {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.response.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn respond(request: impl Into<Vec<u8>>) -> String {
+        let mut stream = FakeStream { request: io::Cursor::new(request.into()), response: Vec::new() };
+        handle_connection(&mut stream);
+        String::from_utf8_lossy(&stream.response).into_owned()
+    }
+
+    #[test]
+    fn directories_are_not_found() {
+        // The tests run in the folder of the crate, where 404.html is.
+        fs::create_dir_all("files/some_dir").unwrap();
+        let response = respond("GET /files/some_dir HTTP/1.1\r\n\r\n");
+        fs::remove_dir("files/some_dir").unwrap();
+        let _ = fs::remove_dir("files");
+
+        assert!(response.starts_with("HTTP/1.1 404 NOT FOUND\r\n"), "{}", response);
+        assert!(response.ends_with(&fs::read_to_string("404.html").unwrap()));
+    }
+
+    #[test]
+    fn headers_have_a_size_limit() {
+        let endless = vec![b'a'; MAX_HEADER_BYTES as usize + 1];
+        assert!(respond(endless).starts_with("HTTP/1.1 431 "));
+
+        let mut many_headers = b"GET / HTTP/1.1\r\n".to_vec();
+        many_headers.extend(b"X-Padding: a\r\n".repeat(1000));
+        assert!(respond(many_headers).starts_with("HTTP/1.1 431 "));
+
+        assert!(respond("GET / HTTP/1.1\r\nHost: local").starts_with("HTTP/1.1 400 "));
+        assert!(respond("GET / HTTP/1.1\r\n\r\n").starts_with("HTTP/1.1 200 OK\r\n"));
+    }
 }
@@ -0,0 +1,347 @@
+// Support for HTTP byte-range requests (RFC 9110, section 14).
+//
+// A client can ask for only parts of a file with a header like:
+//
+//      Range: bytes=0-499          -> the first 500 bytes
+//      Range: bytes=500-           -> everything from byte 500 to the end
+//      Range: bytes=-500           -> the last 500 bytes
+//      Range: bytes=0-99,200-299   -> several ranges at once (multipart/byteranges)
+//
+// Instead of reading the whole file into a String, we seek to the start of each
+// range, and only copy the bytes that were asked for straight into the stream.
+// This is what makes resumable downloads of large files work.
+
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// An inclusive range of bytes, `start..=end`, just like in the `Range` header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl ByteRange {
+    // A ByteRange always contains at least one byte, because the end is inclusive.
+    fn len(&self) -> u64 {
+        self.end - self.start + 1
+    }
+}
+
+/// What we should send back, given the `Range` header of a request.
+#[derive(Debug, PartialEq, Eq)]
+pub enum RangeRequest {
+    /// No (usable) `Range` header -> 200 OK with the whole file.
+    Full,
+    /// One or more satisfiable ranges -> 206 Partial Content.
+    Partial(Vec<ByteRange>),
+    /// None of the ranges overlap the file -> 416 Range Not Satisfiable.
+    Unsatisfiable,
+}
+
+/// Parses the value of a `Range` header, for a file that is `len` bytes long.
+///
+/// A header we do not understand is ignored (as the RFC tells us to), so the
+/// whole file is sent. Ranges that start past the end of the file are dropped,
+/// and if no ranges are left, the request is unsatisfiable. Ranges that overlap
+/// or touch each other are merged into one, so no byte is sent twice.
+pub fn parse_range(header: Option<&str>, len: u64) -> RangeRequest {
+    let header = match header {
+        Some(header) => header.trim(),
+        None => return RangeRequest::Full,
+    };
+
+    // We only know about byte ranges.
+    let specs = match header.strip_prefix("bytes=") {
+        Some(specs) => specs,
+        None => return RangeRequest::Full,
+    };
+
+    let mut ranges = Vec::new();
+
+    for spec in specs.split(',') {
+        let (first, last) = match spec.trim().split_once('-') {
+            Some(parts) => parts,
+            None => return RangeRequest::Full,
+        };
+
+        let range = match (first.trim(), last.trim()) {
+            // "-500" -> the last 500 bytes.
+            ("", suffix) => match suffix.parse::<u64>() {
+                Ok(0) => None,
+                Ok(_) if len == 0 => None,
+                Ok(suffix) => Some(ByteRange {
+                    start: len.saturating_sub(suffix),
+                    end: len - 1,
+                }),
+                Err(_) => return RangeRequest::Full,
+            },
+            // "500-" -> from byte 500 to the end.
+            (start, "") => match start.parse::<u64>() {
+                Ok(start) if start < len => Some(ByteRange { start, end: len - 1 }),
+                Ok(_) => None,
+                Err(_) => return RangeRequest::Full,
+            },
+            // "0-499" -> the end is clamped to the last byte of the file.
+            (start, end) => match (start.parse::<u64>(), end.parse::<u64>()) {
+                (Ok(start), Ok(end)) if start > end => return RangeRequest::Full,
+                (Ok(start), Ok(end)) if start < len => Some(ByteRange {
+                    start,
+                    end: end.min(len - 1),
+                }),
+                (Ok(_), Ok(_)) => None,
+                _ => return RangeRequest::Full,
+            },
+        };
+
+        if let Some(range) = range {
+            ranges.push(range);
+        }
+    }
+
+    if ranges.is_empty() {
+        RangeRequest::Unsatisfiable
+    } else {
+        RangeRequest::Partial(coalesce(ranges))
+    }
+}
+
+// Sorts the ranges, and merges the ones that overlap or are next to each other,
+// e.g. "0-9,5-19,20-29" becomes "0-29". Otherwise a client could make us send the
+// same bytes over and over again, with a header like "0-,0-,0-,0-".
+fn coalesce(mut ranges: Vec<ByteRange>) -> Vec<ByteRange> {
+    ranges.sort_by_key(|range| range.start);
+
+    let mut merged: Vec<ByteRange> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end.saturating_add(1) => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+/// Streams the whole file, e.g. "HTTP/1.1 200 OK" or "HTTP/1.1 404 NOT FOUND".
+pub fn write_full<R: Read, W: Write>(
+    out: &mut W,
+    status_line: &str,
+    content_type: &str,
+    file: &mut R,
+    len: u64,
+) -> io::Result<()> {
+    write!(
+        out,
+        "{}\r\nContent-Type: {}\r\nContent-Length: {}\r\nAccept-Ranges: bytes\r\n\r\n",
+        status_line, content_type, len
+    )?;
+    io::copy(file, out)?;
+    out.flush()
+}
+
+/// Streams the requested ranges with "206 Partial Content".
+///
+/// A single range is sent as the body directly, with a `Content-Range` header.
+/// Several ranges are sent as a `multipart/byteranges` body, where each part has
+/// its own `Content-Type` and `Content-Range` headers.
+pub fn write_partial<R: Read + Seek, W: Write>(
+    out: &mut W,
+    content_type: &str,
+    file: &mut R,
+    len: u64,
+    ranges: &[ByteRange],
+) -> io::Result<()> {
+    if let [range] = ranges {
+        write!(
+            out,
+            "HTTP/1.1 206 Partial Content\r\nContent-Type: {}\r\nContent-Length: {}\r\nContent-Range: bytes {}-{}/{}\r\nAccept-Ranges: bytes\r\n\r\n",
+            content_type,
+            range.len(),
+            range.start,
+            range.end,
+            len
+        )?;
+        copy_range(file, out, range)?;
+        return out.flush();
+    }
+
+    let boundary = boundary();
+
+    // We need to know the Content-Length up front, so we build the headers of every part first.
+    let part_headers: Vec<String> = ranges
+        .iter()
+        .map(|range| {
+            format!(
+                "\r\n--{}\r\nContent-Type: {}\r\nContent-Range: bytes {}-{}/{}\r\n\r\n",
+                boundary, content_type, range.start, range.end, len
+            )
+        })
+        .collect();
+    let closing = format!("\r\n--{}--\r\n", boundary);
+
+    let content_length = part_headers.iter().map(|h| h.len() as u64).sum::<u64>()
+        + ranges.iter().map(ByteRange::len).sum::<u64>()
+        + closing.len() as u64;
+
+    write!(
+        out,
+        "HTTP/1.1 206 Partial Content\r\nContent-Type: multipart/byteranges; boundary={}\r\nContent-Length: {}\r\nAccept-Ranges: bytes\r\n\r\n",
+        boundary, content_length
+    )?;
+
+    for (headers, range) in part_headers.iter().zip(ranges) {
+        out.write_all(headers.as_bytes())?;
+        copy_range(file, out, range)?;
+    }
+
+    out.write_all(closing.as_bytes())?;
+    out.flush()
+}
+
+/// Answers with "416 Range Not Satisfiable", telling the client how big the file really is.
+pub fn write_unsatisfiable<W: Write>(out: &mut W, len: u64) -> io::Result<()> {
+    write!(
+        out,
+        "HTTP/1.1 416 Range Not Satisfiable\r\nContent-Range: bytes */{}\r\nContent-Length: 0\r\n\r\n",
+        len
+    )?;
+    out.flush()
+}
+
+// Seek to the start of the range, and copy only the bytes of the range.
+// take() makes sure we stop reading after range.len() bytes.
+fn copy_range<R: Read + Seek, W: Write>(file: &mut R, out: &mut W, range: &ByteRange) -> io::Result<()> {
+    file.seek(SeekFrom::Start(range.start))?;
+    let copied = io::copy(&mut file.take(range.len()), out)?;
+
+    if copied != range.len() {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "file got shorter while it was being sent",
+        ));
+    }
+
+    Ok(())
+}
+
+// The boundary must not show up inside of the parts, so we make it hard to guess.
+fn boundary() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+
+    format!("BYTERANGES{:x}", nanos)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn range(start: u64, end: u64) -> ByteRange {
+        ByteRange { start, end }
+    }
+
+    #[test]
+    fn no_header_is_full() {
+        assert_eq!(RangeRequest::Full, parse_range(None, 100));
+    }
+
+    #[test]
+    fn single_ranges() {
+        assert_eq!(RangeRequest::Partial(vec![range(0, 49)]), parse_range(Some("bytes=0-49"), 100));
+        assert_eq!(RangeRequest::Partial(vec![range(90, 99)]), parse_range(Some("bytes=90-"), 100));
+        assert_eq!(RangeRequest::Partial(vec![range(80, 99)]), parse_range(Some("bytes=-20"), 100));
+        // The end and the suffix get clamped to the file.
+        assert_eq!(RangeRequest::Partial(vec![range(50, 99)]), parse_range(Some("bytes=50-500"), 100));
+        assert_eq!(RangeRequest::Partial(vec![range(0, 99)]), parse_range(Some("bytes=-500"), 100));
+    }
+
+    #[test]
+    fn multiple_ranges() {
+        assert_eq!(
+            RangeRequest::Partial(vec![range(0, 9), range(20, 29), range(95, 99)]),
+            parse_range(Some("bytes=0-9, 20-29,-5"), 100)
+        );
+    }
+
+    #[test]
+    fn overlapping_and_adjacent_ranges_are_merged() {
+        assert_eq!(
+            RangeRequest::Partial(vec![range(0, 29), range(50, 59)]),
+            parse_range(Some("bytes=50-59,5-19,0-9,20-29"), 100)
+        );
+        assert_eq!(RangeRequest::Partial(vec![range(0, 99)]), parse_range(Some("bytes=0-,0-,-10"), 100));
+        // Ranges with a gap between them stay apart, even if the gap is one byte.
+        assert_eq!(
+            RangeRequest::Partial(vec![range(0, 9), range(11, 19)]),
+            parse_range(Some("bytes=11-19,0-9"), 100)
+        );
+    }
+
+    #[test]
+    fn unsatisfiable_ranges() {
+        assert_eq!(RangeRequest::Unsatisfiable, parse_range(Some("bytes=100-"), 100));
+        assert_eq!(RangeRequest::Unsatisfiable, parse_range(Some("bytes=200-300"), 100));
+        assert_eq!(RangeRequest::Unsatisfiable, parse_range(Some("bytes=-0"), 100));
+        assert_eq!(RangeRequest::Unsatisfiable, parse_range(Some("bytes=0-"), 0));
+    }
+
+    #[test]
+    fn unsatisfiable_ranges_are_dropped() {
+        assert_eq!(
+            RangeRequest::Partial(vec![range(0, 9)]),
+            parse_range(Some("bytes=0-9,500-600"), 100)
+        );
+    }
+
+    #[test]
+    fn malformed_header_is_ignored() {
+        assert_eq!(RangeRequest::Full, parse_range(Some("items=0-9"), 100));
+        assert_eq!(RangeRequest::Full, parse_range(Some("bytes=abc"), 100));
+        assert_eq!(RangeRequest::Full, parse_range(Some("bytes=9-0"), 100));
+        assert_eq!(RangeRequest::Full, parse_range(Some("bytes=0-9,x-"), 100));
+    }
+
+    #[test]
+    fn writes_single_range() {
+        let mut file = Cursor::new(b"Hello, range requests!".to_vec());
+        let mut out = Vec::new();
+
+        write_partial(&mut out, "text/plain", &mut file, 22, &[range(7, 11)]).unwrap();
+
+        let response = String::from_utf8(out).unwrap();
+        assert!(response.starts_with("HTTP/1.1 206 Partial Content\r\n"));
+        assert!(response.contains("Content-Length: 5\r\n"));
+        assert!(response.contains("Content-Range: bytes 7-11/22\r\n"));
+        assert!(response.ends_with("\r\n\r\nrange"));
+    }
+
+    #[test]
+    fn writes_multipart_byteranges() {
+        let mut file = Cursor::new(b"0123456789".to_vec());
+        let mut out = Vec::new();
+
+        write_partial(&mut out, "text/plain", &mut file, 10, &[range(0, 1), range(8, 9)]).unwrap();
+
+        let response = String::from_utf8(out).unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        let boundary = head.split("boundary=").nth(1).unwrap().lines().next().unwrap();
+
+        assert!(head.contains(&format!("Content-Length: {}", body.len())));
+        assert!(body.contains("Content-Range: bytes 0-1/10\r\n\r\n01\r\n"));
+        assert!(body.contains("Content-Range: bytes 8-9/10\r\n\r\n89\r\n"));
+        assert!(body.ends_with(&format!("--{}--\r\n", boundary)));
+    }
+
+    #[test]
+    fn writes_unsatisfiable() {
+        let mut out = Vec::new();
+        write_unsatisfiable(&mut out, 42).unwrap();
+
+        let response = String::from_utf8(out).unwrap();
+        assert!(response.starts_with("HTTP/1.1 416 Range Not Satisfiable\r\n"));
+        assert!(response.contains("Content-Range: bytes */42\r\n"));
+    }
+}